@@ -1,4 +1,4 @@
-use byte_trie::ByteTrie;
+use byte_trie::prelude::*;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use rand::distributions::Alphanumeric;
 use rand::prelude::*;
@@ -13,8 +13,8 @@ fn random_string(rng: &mut ThreadRng, length: usize) -> String {
 fn inserting(fake_commit_oids: &[(Vec<u8>, String)]) -> ByteTrie<&String> {
     let mut trie = ByteTrie::new();
     fake_commit_oids
-        .into_iter()
-        .for_each(|(oid, summary)| trie.insert(oid.clone(), summary));
+        .iter()
+        .for_each(|(oid, summary)| trie.insert(oid, summary));
     trie
 }
 
@@ -28,9 +28,16 @@ fn criterion_benchmark(c: &mut Criterion) {
         })
         .collect();
 
+    let mut sorted = oids.clone();
+    sorted.sort();
+
     c.bench_function("inserting 1,000", move |b| {
         b.iter(|| inserting(black_box(&oids)))
     });
+
+    c.bench_function("from sorted 1,000", move |b| {
+        b.iter(|| ByteTrie::from_sorted_iter(black_box(&sorted).iter().map(|(k, v)| (k, v))))
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
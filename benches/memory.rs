@@ -0,0 +1,70 @@
+//! Heap usage of tries holding fake commit oids.
+//!
+//! Not a criterion benchmark, it counts the bytes allocated while building
+//! each trie and prints them.  1,000,000 oids is about the number of commits in
+//! the linux repository.
+
+use byte_trie::prelude::*;
+use rand::prelude::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Print the bytes allocated since `before`
+fn report(name: &str, before: usize, len: usize) {
+    let used = ALLOCATED.load(Ordering::Relaxed) - before;
+    println!(
+        "{:<12} {:>6} MiB, {:>4} bytes per oid",
+        name,
+        used >> 20,
+        used / len
+    );
+}
+
+fn heap_usage<T: BytesTrie<u32>>(name: &str, oids: &[[u8; 20]]) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let mut trie = T::new();
+    for (i, oid) in oids.iter().enumerate() {
+        trie.insert(oid, i as u32);
+    }
+
+    report(name, before, oids.len());
+}
+
+fn fixed_heap_usage(oids: &[[u8; 20]]) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let mut trie = Sha1Trie::new();
+    for (i, oid) in oids.iter().enumerate() {
+        trie.insert(oid, i as u32).unwrap();
+    }
+
+    report("Sha1Trie", before, oids.len());
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let oids: Vec<[u8; 20]> = (0..1_000_000).map(|_| rng.gen()).collect();
+
+    heap_usage::<ByteTrie<u32>>("ByteTrie", &oids);
+    heap_usage::<NibbleTrie<u32>>("NibbleTrie", &oids);
+    heap_usage::<BitTrie<u32>>("BitTrie", &oids);
+    fixed_heap_usage(&oids);
+}
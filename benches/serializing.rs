@@ -1,4 +1,4 @@
-use byte_trie::ByteTrie;
+use byte_trie::prelude::*;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use rand::distributions::Alphanumeric;
 use rand::prelude::*;
@@ -13,8 +13,8 @@ fn random_string(rng: &mut ThreadRng, length: usize) -> String {
 fn serializing(fake_commit_oids: &[(Vec<u8>, String)]) -> String {
     let mut trie = ByteTrie::new();
     fake_commit_oids
-        .into_iter()
-        .for_each(|(oid, summary)| trie.insert(oid.clone(), summary));
+        .iter()
+        .for_each(|(oid, summary)| trie.insert(oid, summary));
     serde_json::to_string(&trie).unwrap()
 }
 
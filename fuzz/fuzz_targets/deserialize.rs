@@ -0,0 +1,58 @@
+//! Deserializes untrusted JSON and CBOR dumps into all three tries.
+//!
+//! Input layout is `[trie] [format] [dump]`, where `trie` picks the trie type
+//! by `% 3` and `format` picks JSON or CBOR by `% 2`.  A dump that reads has
+//! to give every key back through `get`, and write and read back to the same
+//! entries.
+#![no_main]
+
+use byte_trie::prelude::*;
+use libfuzzer_sys::fuzz_target;
+
+macro_rules! run {
+    ($trie:ty, $cbor:expr, $dump:expr) => {{
+        let read = |dump: &[u8]| -> Option<$trie> {
+            if $cbor {
+                ciborium::from_reader(dump).ok()
+            } else {
+                serde_json::from_slice(dump).ok()
+            }
+        };
+
+        if let Some(trie) = read($dump) {
+            let entries: Vec<_> = trie
+                .iter()
+                .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+                .collect();
+            for (key, value) in &entries {
+                assert_eq!(trie.get(key), Some(value));
+            }
+            assert_eq!(trie.len(), entries.len());
+
+            let dump = if $cbor {
+                let mut dump = Vec::new();
+                ciborium::into_writer(&trie, &mut dump).unwrap();
+                dump
+            } else {
+                serde_json::to_vec(&trie).unwrap()
+            };
+            let again: Vec<_> = read(&dump)
+                .unwrap()
+                .iter()
+                .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+                .collect();
+            assert_eq!(again, entries);
+        }
+    }};
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let [kind, format, dump @ ..] = data {
+        let cbor = format % 2 == 1;
+        match kind % 3 {
+            0 => run!(ByteTrie<u8>, cbor, dump),
+            1 => run!(NibbleTrie<u8>, cbor, dump),
+            _ => run!(BitTrie<u8>, cbor, dump),
+        }
+    }
+});
@@ -0,0 +1,108 @@
+//! Feeds arbitrary insert and remove sequences into all three tries.
+//!
+//! Input layout is `[trie] ([op] [key; op & 0x7f] [value]?)*`, where `trie`
+//! picks the trie type by `% 3`.  An `op` with the high bit set removes the
+//! key and has no value byte.  A truncated trailing operation is dropped.
+//! Every trie is checked against a `BTreeMap`, and so is what it reads back
+//! from its JSON and CBOR dumps.  Its entries are then dealt out into two
+//! tries and appended back together, which has to give the same entries
+//! again, after checking that the set operations see the two halves as
+//! disjoint.
+#![no_main]
+
+use byte_trie::prelude::*;
+use libfuzzer_sys::fuzz_target;
+use std::collections::BTreeMap;
+
+enum Op<'a> {
+    Insert(&'a [u8], u8),
+    Remove(&'a [u8]),
+}
+
+fn ops(data: &[u8]) -> Vec<Op<'_>> {
+    let mut ops = Vec::new();
+    let mut rest = data;
+
+    while let Some((&op, tail)) = rest.split_first() {
+        let len = (op & 0x7f) as usize;
+        let remove = op & 0x80 != 0;
+        if tail.len() < len + !remove as usize {
+            break;
+        }
+
+        if remove {
+            ops.push(Op::Remove(&tail[..len]));
+            rest = &tail[len..];
+        } else {
+            ops.push(Op::Insert(&tail[..len], tail[len]));
+            rest = &tail[len + 1..];
+        }
+    }
+
+    ops
+}
+
+macro_rules! run {
+    ($trie:ty, $key:ty, $ops:expr) => {{
+        let mut trie = <$trie>::new();
+        let mut model = BTreeMap::new();
+
+        for op in $ops {
+            match op {
+                Op::Insert(key, value) => {
+                    trie.insert(key, value);
+                    model.insert(key.to_vec(), value);
+                }
+                Op::Remove(key) => assert_eq!(trie.remove(key), model.remove(key)),
+            }
+        }
+
+        let entries: Vec<_> = trie
+            .iter()
+            .map(|(k, &v)| (k.iter().collect::<Vec<_>>(), v))
+            .collect();
+        let expected: Vec<_> = model
+            .into_iter()
+            .map(|(k, v)| (<$key>::symbols(&k).collect::<Vec<_>>(), v))
+            .collect();
+        assert_eq!(entries, expected);
+
+        let json: $trie = serde_json::from_str(&serde_json::to_string(&trie).unwrap()).unwrap();
+        let mut dump = Vec::new();
+        ciborium::into_writer(&trie, &mut dump).unwrap();
+        let cbor: $trie = ciborium::from_reader(&dump[..]).unwrap();
+        for read in [json, cbor] {
+            let entries: Vec<_> = read
+                .iter()
+                .map(|(k, &v)| (k.iter().collect::<Vec<_>>(), v))
+                .collect();
+            assert_eq!(entries, expected);
+        }
+
+        let (mut even, mut odd) = (<$trie>::new(), <$trie>::new());
+        for (i, (key, &value)) in trie.iter().enumerate() {
+            let half = if i % 2 == 0 { &mut even } else { &mut odd };
+            half.insert_key(key, value);
+        }
+        assert!(even.intersection(&odd).next().is_none());
+        assert_eq!(even.difference(&odd).count(), even.len());
+        assert_eq!(even.symmetric_difference(&odd).count(), expected.len());
+        even.append(&mut odd);
+        let appended: Vec<_> = even
+            .iter()
+            .map(|(k, &v)| (k.iter().collect::<Vec<_>>(), v))
+            .collect();
+        assert_eq!(appended, expected);
+    }};
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((&kind, rest)) = data.split_first() {
+        let ops = ops(rest);
+        match kind % 3 {
+            0 => run!(ByteTrie<u8>, ByteKey, ops),
+            1 => run!(NibbleTrie<u8>, NibbleKey, ops),
+            _ => run!(BitTrie<u8>, BitKey, ops),
+        }
+    }
+});
@@ -11,7 +11,6 @@
 use crate::AdaptiveNode;
 use crate::BytesKey;
 use std::fmt;
-use std::fmt::Pointer;
 
 /// Maximum size of a child slice
 pub(crate) const MAX_CHILD_SIZE: usize = 256;
@@ -85,7 +84,6 @@ impl<K: BytesKey, T> Child<K, T> {
         }
     }
 
-    #[cfg(feature = "serde")]
     pub(crate) fn get(&self) -> &[Option<AdaptiveNode<K, T>>] {
         match self {
             Child::_1(c) => c.as_ref().as_ref(),
@@ -100,29 +98,28 @@ impl<K: BytesKey, T> Child<K, T> {
         }
     }
 
-    #[cfg(feature = "serde")]
     pub(crate) fn is_empty(&self) -> bool {
         self.get().iter().all(Option::is_none)
     }
+
+    /// Remove and return the only node in the child, if there is exactly one
+    pub(crate) fn take_only(&mut self) -> Option<AdaptiveNode<K, T>> {
+        let mut occupied = self.get_mut().iter_mut().filter(|slot| slot.is_some());
+        match (occupied.next(), occupied.next()) {
+            (Some(only), None) => only.take(),
+            _ => None,
+        }
+    }
 }
 
-// I'm not sure why this works but deriving `Debug` on the enum doesn't.
-// It was complaining about the arrays larger than 32, but it seems like
-// `fmt()` is just doing the same thing to the box?  My guess is that
-// deriving it dereferences `Box` or something similar.
-impl<K: BytesKey, T: fmt::Debug> fmt::Debug for Child<K, T> {
+// Deriving `Debug` used to fail on arrays longer than 32, and would print the
+// slots wrapped in the size variant, like `_4([..])`.  So we print the slots
+// directly.  This used to call `c.fmt(f)` on the `Box` with `fmt::Pointer`
+// imported, and since `Debug` wasn't implemented for it that resolved to
+// `Pointer::fmt`, printing the address of the slots.
+impl<K: BytesKey + fmt::Debug, T: fmt::Debug> fmt::Debug for Child<K, T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Child::_1(c) => c.fmt(f),
-            Child::_2(c) => c.fmt(f),
-            Child::_4(c) => c.fmt(f),
-            Child::_8(c) => c.fmt(f),
-            Child::_16(c) => c.fmt(f),
-            Child::_32(c) => c.fmt(f),
-            Child::_64(c) => c.fmt(f),
-            Child::_128(c) => c.fmt(f),
-            Child::_256(c) => c.fmt(f),
-        }
+        fmt::Debug::fmt(self.get(), f)
     }
 }
 
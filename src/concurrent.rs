@@ -0,0 +1,215 @@
+//! A trie for many concurrent readers and a single writer.
+//!
+//! The trie is published as a `PersistentTrie` version behind an atomic
+//! pointer.  Writers build the next version by path copying while only
+//! holding the writer lock, then swap the pointer, read-copy-update style.
+//! Readers load the pointer without taking any lock, so they never wait on a
+//! writer, not even one stalled in the middle of a write.  Old versions are
+//! freed once the last reader holding them is done, and a writer drops the
+//! version it replaced after releasing the writer lock.
+//!
+//! # Linearizability
+//!
+//! `insert`, `remove` and `update` take effect when their version is
+//! published, and `get`, `contains_key` and `snapshot` observe the version
+//! published at the moment they load the pointer.  All of these operations
+//! are linearizable.  Iterating over a snapshot sees a single consistent
+//! version, but writes published during the iteration are not visible to it.
+
+use crate::keys::{BitKey, ByteKey, NibbleKey};
+use crate::persistent::PersistentTrie;
+use crate::BytesKey;
+use arc_swap::ArcSwap;
+use std::sync::{Arc, Mutex};
+
+/// A trie with lock-free reads of consistent snapshots and serialized writes.
+pub struct ConcurrentTrie<K: BytesKey, V> {
+    published: ArcSwap<PersistentTrie<K, V>>,
+    writer: Mutex<()>,
+}
+
+/// A concurrent `u8` based Trie represented with bytes.
+pub type ConcurrentByteTrie<T> = ConcurrentTrie<ByteKey, T>;
+
+/// A concurrent `u8` based Trie represented with nibbles.
+pub type ConcurrentNibbleTrie<T> = ConcurrentTrie<NibbleKey, T>;
+
+/// A concurrent `u8` based Trie represented with bits.
+pub type ConcurrentBitTrie<T> = ConcurrentTrie<BitKey, T>;
+
+impl<K: BytesKey + Clone, V> ConcurrentTrie<K, V> {
+    /// Create an empty trie
+    pub fn new() -> Self {
+        Self::from_persistent(PersistentTrie::new())
+    }
+
+    /// Publish an existing persistent trie as the first version
+    pub fn from_persistent(trie: PersistentTrie<K, V>) -> Self {
+        Self {
+            published: ArcSwap::from_pointee(trie),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// The currently published version.
+    ///
+    /// The snapshot is unaffected by later writes and can be read for as long
+    /// as it is kept.
+    pub fn snapshot(&self) -> PersistentTrie<K, V> {
+        PersistentTrie::clone(&self.published.load())
+    }
+
+    /// Get the value of a key in the currently published version
+    pub fn get(&self, key: &[u8]) -> Option<Arc<V>> {
+        self.published.load().get_shared(key).cloned()
+    }
+
+    /// If the currently published version contains a value for the key
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.published.load().contains_key(key)
+    }
+
+    /// Insert a key and value, publishing a new version
+    pub fn insert(&self, key: &[u8], value: V) {
+        self.update(|trie| trie.insert(key, value));
+    }
+
+    /// Remove a key, publishing a new version if it existed
+    pub fn remove(&self, key: &[u8]) -> Option<Arc<V>> {
+        let mut removed = None;
+        self.write(|trie| {
+            removed = trie.get_shared(key).cloned();
+            removed.as_ref().map(|_| trie.remove(key))
+        });
+        removed
+    }
+
+    /// Build and publish the next version from the current one.
+    ///
+    /// Writers are serialized, so no other write is published between reading
+    /// the current version and publishing the new one.  Use this to make
+    /// several changes visible to readers at once.
+    pub fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&PersistentTrie<K, V>) -> PersistentTrie<K, V>,
+    {
+        self.write(|trie| Some(f(trie)));
+    }
+
+    /// Publish the version `f` builds, if it builds one, under the writer lock
+    fn write<F>(&self, f: F)
+    where
+        F: FnOnce(&PersistentTrie<K, V>) -> Option<PersistentTrie<K, V>>,
+    {
+        let writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let replaced = f(&self.published.load()).map(|next| self.published.swap(Arc::new(next)));
+
+        // freeing the nodes only this version had doesn't hold up other writers
+        drop(writer);
+        drop(replaced);
+    }
+}
+
+impl<K: BytesKey + Clone, V> Default for ConcurrentTrie<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn readers_see_consistent_versions() {
+        const WRITES: u32 = 5_000;
+        let trie = Arc::new(ConcurrentByteTrie::new());
+        let done = Arc::new(AtomicBool::new(false));
+
+        let readers: Vec<_> = (0..8)
+            .map(|_| {
+                let (trie, done) = (trie.clone(), done.clone());
+                thread::spawn(move || {
+                    let mut last_len = 0;
+                    while !done.load(Ordering::Acquire) {
+                        // keys are written in order, so every version is a prefix of them
+                        let snapshot = trie.snapshot();
+                        let values: Vec<u32> = snapshot.iter().map(|(_, &v)| v).collect();
+                        assert!(values.iter().copied().eq(0..values.len() as u32));
+                        assert!(values.len() >= last_len);
+                        last_len = values.len();
+
+                        if let Some(&last) = values.last() {
+                            assert_eq!(trie.get(&last.to_be_bytes()).as_deref(), Some(&last));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for i in 0..WRITES {
+            trie.insert(&i.to_be_bytes(), i);
+            if i % 3 == 0 {
+                // removing a missing key publishes nothing
+                let published = trie.published.load_full();
+                assert!(trie.remove(&(WRITES + i).to_be_bytes()).is_none());
+                assert!(Arc::ptr_eq(&published, &trie.published.load_full()));
+            }
+        }
+        done.store(true, Ordering::Release);
+
+        readers
+            .into_iter()
+            .for_each(|reader| reader.join().unwrap());
+        assert_eq!(trie.snapshot().iter().count(), WRITES as usize);
+        assert_eq!(trie.remove(&0u32.to_be_bytes()).as_deref(), Some(&0));
+    }
+
+    #[test]
+    fn readers_pass_a_stalled_writer() {
+        let trie = Arc::new(ConcurrentByteTrie::new());
+        trie.insert(b"a", 1);
+
+        let (stalled, wait_stalled) = mpsc::channel();
+        let (resume, wait_resume) = mpsc::channel::<()>();
+        let writer = {
+            let trie = trie.clone();
+            thread::spawn(move || {
+                trie.update(|current| {
+                    stalled.send(()).unwrap();
+                    wait_resume.recv().unwrap();
+                    current.insert(b"b", 2)
+                })
+            })
+        };
+
+        // the writer holds the writer lock until it is resumed
+        wait_stalled.recv().unwrap();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let trie = trie.clone();
+                thread::spawn(move || {
+                    for _ in 0..1_000 {
+                        assert_eq!(trie.get(b"a").as_deref(), Some(&1));
+                        assert!(!trie.contains_key(b"b"));
+                        assert_eq!(trie.snapshot().iter().count(), 1);
+                    }
+                })
+            })
+            .collect();
+        readers
+            .into_iter()
+            .for_each(|reader| reader.join().unwrap());
+
+        resume.send(()).unwrap();
+        writer.join().unwrap();
+        assert_eq!(trie.get(b"b").as_deref(), Some(&2));
+    }
+}
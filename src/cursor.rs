@@ -0,0 +1,537 @@
+//! Cursors for stepping through a trie in key order.
+//!
+//! A cursor keeps the path from the root down to its current entry, along
+//! with the sorted children of every node on it.  Stepping and seeking only
+//! pop as much of that path as they need before walking back down.
+//!
+//! `Cursor` keeps references to the nodes on its path.  `CursorMut` can't
+//! hold on to those while handing out mutable values, so it keeps the bucket
+//! slots leading to each node instead and follows them from the root when it
+//! needs a node.  Removing an entry can restructure the nodes on the path, so
+//! that is the only time it walks down to the next key again.
+//!
+//! Both cursors have a "ghost" position that sits before the first entry and
+//! after the last one.  New cursors start on it, and stepping off either end
+//! of the trie lands back on it.
+
+use crate::{AdaptiveNode, BytesKey};
+use std::mem;
+
+struct Frame<N> {
+    /// The node, or how to reach it from the parent frame's node
+    node: N,
+    /// The full key of `node`, including its own fragment
+    key: Vec<u8>,
+    has_value: bool,
+    /// The nodes below, sorted by their first symbol
+    children: Vec<(u8, N)>,
+    /// Position of `node` in the parent frame's `children`
+    index: usize,
+}
+
+/// How a path reaches the nodes of its frames
+trait Nodes {
+    type Node;
+
+    /// The frame of the root node
+    fn root(&self) -> Frame<Self::Node>;
+
+    /// The frame of child `index` of the top frame of `path`
+    fn child(&self, path: &[Frame<Self::Node>], index: usize) -> Frame<Self::Node>;
+}
+
+impl<'a, K: BytesKey, V> Nodes for &'a AdaptiveNode<K, V> {
+    type Node = &'a AdaptiveNode<K, V>;
+
+    fn root(&self) -> Frame<Self::Node> {
+        let children = self.children().into_iter().map(|c| (c.key.symbol(0), c));
+        frame(*self, self, &[], children.collect(), 0)
+    }
+
+    fn child(&self, path: &[Frame<Self::Node>], index: usize) -> Frame<Self::Node> {
+        let parent = path.last().expect("pushing a child onto an empty path");
+        let node = parent.children[index].1;
+        let children = node.children().into_iter().map(|c| (c.key.symbol(0), c));
+        frame(node, node, &parent.key, children.collect(), index)
+    }
+}
+
+/// Bucket slots from a node down to one of its children, one for each empty
+/// keyed node holding collided slots on the way
+type Slots = Vec<usize>;
+
+/// The nodes of a `CursorMut`, found by following bucket slots from the root
+struct Slotted<'r, K: BytesKey, V>(&'r AdaptiveNode<K, V>);
+
+impl<K: BytesKey, V> Nodes for Slotted<'_, K, V> {
+    type Node = Slots;
+
+    fn root(&self) -> Frame<Slots> {
+        frame(Slots::new(), self.0, &[], child_slots(self.0), 0)
+    }
+
+    fn child(&self, path: &[Frame<Slots>], index: usize) -> Frame<Slots> {
+        let parent = path.last().expect("pushing a child onto an empty path");
+        let slots = parent.children[index].1.clone();
+        let node = below(follow(self.0, path), &slots);
+        frame(slots, node, &parent.key, child_slots(node), index)
+    }
+}
+
+fn frame<N, K: BytesKey, V>(
+    handle: N,
+    node: &AdaptiveNode<K, V>,
+    parent_key: &[u8],
+    children: Vec<(u8, N)>,
+    index: usize,
+) -> Frame<N> {
+    let mut key = Vec::with_capacity(parent_key.len() + node.key.symbol_len());
+    key.extend_from_slice(parent_key);
+    key.extend(node.key.iter());
+
+    Frame {
+        node: handle,
+        key,
+        has_value: node.value.is_some(),
+        children,
+        index,
+    }
+}
+
+/// The slots of the nodes below `node`, sorted like `AdaptiveNode::children`
+fn child_slots<K: BytesKey, V>(node: &AdaptiveNode<K, V>) -> Vec<(u8, Slots)> {
+    fn collect<K: BytesKey, V>(
+        node: &AdaptiveNode<K, V>,
+        slots: &mut Slots,
+        children: &mut Vec<(u8, Slots)>,
+    ) {
+        let child = match &node.child {
+            Some(child) => child,
+            None => return,
+        };
+
+        for (slot, node) in child.get().iter().enumerate() {
+            if let Some(node) = node {
+                slots.push(slot);
+                if node.key.symbol_len() == 0 {
+                    collect(node, slots, children);
+                } else {
+                    children.push((node.key.symbol(0), slots.clone()));
+                }
+                slots.pop();
+            }
+        }
+    }
+
+    let mut children = Vec::new();
+    collect(node, &mut Slots::new(), &mut children);
+    children.sort_unstable_by_key(|(symbol, _)| *symbol);
+    children
+}
+
+/// The node `slots` lead to from `node`
+fn below<'r, K: BytesKey, V>(
+    node: &'r AdaptiveNode<K, V>,
+    slots: &[usize],
+) -> &'r AdaptiveNode<K, V> {
+    slots.iter().fold(node, |node, &slot| {
+        node.child
+            .as_ref()
+            .and_then(|child| child.get()[slot].as_ref())
+            .expect("the path leads to a node")
+    })
+}
+
+/// The node of the top frame of `path`
+fn follow<'r, K: BytesKey, V>(
+    root: &'r AdaptiveNode<K, V>,
+    path: &[Frame<Slots>],
+) -> &'r AdaptiveNode<K, V> {
+    path.iter()
+        .skip(1)
+        .fold(root, |node, frame| below(node, &frame.node))
+}
+
+/// The node of the top frame of `path`, mutably
+fn follow_mut<'r, K: BytesKey, V>(
+    root: &'r mut AdaptiveNode<K, V>,
+    path: &[Frame<Slots>],
+) -> &'r mut AdaptiveNode<K, V> {
+    let mut node = root;
+    for &slot in path.iter().skip(1).flat_map(|frame| &frame.node) {
+        node = node
+            .child
+            .as_mut()
+            .and_then(|child| child.get_mut()[slot].as_mut())
+            .expect("the path leads to a node");
+    }
+    node
+}
+
+/// The frames from the root down to the current entry, empty on the ghost position
+struct Path<N> {
+    stack: Vec<Frame<N>>,
+}
+
+impl<N> Path<N> {
+    fn new() -> Self {
+        Self { stack: Vec::new() }
+    }
+
+    fn key(&self) -> Option<&[u8]> {
+        self.stack.last().map(|frame| &frame.key[..])
+    }
+
+    fn move_next(&mut self, nodes: &impl Nodes<Node = N>) {
+        match self.stack.last() {
+            None => {
+                self.stack.push(nodes.root());
+                self.leftmost(nodes);
+            }
+            Some(top) if !top.children.is_empty() => {
+                self.push(nodes, 0);
+                self.leftmost(nodes);
+            }
+            Some(_) => self.skip_subtree(nodes),
+        }
+    }
+
+    fn move_prev(&mut self, nodes: &impl Nodes<Node = N>) {
+        if self.stack.is_empty() {
+            self.stack.push(nodes.root());
+            return self.rightmost(nodes);
+        }
+
+        while let Some(frame) = self.stack.pop() {
+            let parent_has_value = match self.stack.last() {
+                Some(parent) => parent.has_value,
+                None => return,
+            };
+
+            if frame.index > 0 {
+                self.push(nodes, frame.index - 1);
+                return self.rightmost(nodes);
+            }
+
+            // a parent sorts right before its first child
+            if parent_has_value {
+                return;
+            }
+        }
+    }
+
+    /// Move to the first entry at or after `target`, in key representation
+    fn seek(&mut self, nodes: &impl Nodes<Node = N>, target: &[u8]) {
+        while let Some(top) = self.stack.last() {
+            if target.starts_with(&top.key) {
+                break;
+            }
+            self.stack.pop();
+        }
+
+        if self.stack.is_empty() {
+            self.stack.push(nodes.root());
+        }
+
+        while let Some(top) = self.stack.last() {
+            // every key below is at least the node key itself
+            if target <= &top.key[..] {
+                return self.leftmost(nodes);
+            }
+
+            // the node key diverged below the target, so everything below is smaller
+            if !target.starts_with(&top.key) {
+                return self.skip_subtree(nodes);
+            }
+
+            let next = target[top.key.len()];
+            match top.children.iter().position(|&(symbol, _)| symbol >= next) {
+                Some(index) => self.push(nodes, index),
+                None => return self.skip_subtree(nodes),
+            }
+        }
+    }
+
+    /// Push the child at `index` of the top frame
+    fn push(&mut self, nodes: &impl Nodes<Node = N>, index: usize) {
+        let frame = nodes.child(&self.stack, index);
+        self.stack.push(frame);
+    }
+
+    /// Descend to the smallest entry at or below the top frame
+    fn leftmost(&mut self, nodes: &impl Nodes<Node = N>) {
+        while let Some(top) = self.stack.last() {
+            if top.has_value {
+                return;
+            }
+
+            // only an empty root has neither a value or children
+            if top.children.is_empty() {
+                return self.stack.clear();
+            }
+
+            self.push(nodes, 0);
+        }
+    }
+
+    /// Descend to the largest entry at or below the top frame
+    fn rightmost(&mut self, nodes: &impl Nodes<Node = N>) {
+        while let Some(top) = self.stack.last() {
+            if top.children.is_empty() {
+                if !top.has_value {
+                    self.stack.clear();
+                }
+                return;
+            }
+
+            let last = top.children.len() - 1;
+            self.push(nodes, last);
+        }
+    }
+
+    /// Move to the smallest entry after everything below the top frame
+    fn skip_subtree(&mut self, nodes: &impl Nodes<Node = N>) {
+        while let Some(frame) = self.stack.pop() {
+            let has_next = match self.stack.last() {
+                Some(parent) => frame.index + 1 < parent.children.len(),
+                None => false,
+            };
+
+            if has_next {
+                self.push(nodes, frame.index + 1);
+                return self.leftmost(nodes);
+            }
+        }
+    }
+}
+
+/// A cursor over the entries of a trie, in key order.
+pub struct Cursor<'a, K: BytesKey, V> {
+    root: &'a AdaptiveNode<K, V>,
+    path: Path<&'a AdaptiveNode<K, V>>,
+}
+
+impl<'a, K: BytesKey, V> Cursor<'a, K, V> {
+    pub(crate) fn new(root: &'a AdaptiveNode<K, V>) -> Self {
+        Self {
+            root,
+            path: Path::new(),
+        }
+    }
+
+    /// The key of the current entry, `None` on the ghost position
+    pub fn key(&self) -> Option<K> {
+        self.path.key().map(|key| K::new(key.to_vec()))
+    }
+
+    /// The value of the current entry, `None` on the ghost position
+    pub fn value(&self) -> Option<&'a V> {
+        self.path
+            .stack
+            .last()
+            .and_then(|frame| frame.node.value.as_ref())
+    }
+
+    /// The current entry, `None` on the ghost position
+    pub fn key_value(&self) -> Option<(K, &'a V)> {
+        Some((self.key()?, self.value()?))
+    }
+
+    /// Move to the next entry, or from the ghost position to the first entry
+    pub fn move_next(&mut self) {
+        self.path.move_next(&self.root)
+    }
+
+    /// Move to the previous entry, or from the ghost position to the last entry
+    pub fn move_prev(&mut self) {
+        self.path.move_prev(&self.root)
+    }
+
+    /// Move to the first entry with a key greater than or equal to `key`.
+    ///
+    /// `key` is in bytes, like the trie methods.  Only the part of the current
+    /// path that doesn't lead to `key` is walked back up.  Lands on the ghost
+    /// position if every key is smaller.
+    pub fn seek(&mut self, key: &[u8]) {
+        let key: Vec<u8> = K::symbols(key).collect();
+        self.path.seek(&self.root, &key)
+    }
+}
+
+/// A cursor over the entries of a trie that can change the current entry.
+///
+/// Steps and seeks reuse the path like a `Cursor` does, but reaching the
+/// current value follows the path's bucket slots down from the root.
+pub struct CursorMut<'a, K: BytesKey, V> {
+    root: &'a mut AdaptiveNode<K, V>,
+    path: Path<Slots>,
+}
+
+impl<'a, K: BytesKey, V> CursorMut<'a, K, V> {
+    pub(crate) fn new(root: &'a mut AdaptiveNode<K, V>) -> Self {
+        Self {
+            root,
+            path: Path::new(),
+        }
+    }
+
+    /// The key of the current entry, `None` on the ghost position
+    pub fn key(&self) -> Option<K> {
+        self.path.key().map(|key| K::new(key.to_vec()))
+    }
+
+    /// The value of the current entry, `None` on the ghost position
+    pub fn value(&self) -> Option<&V> {
+        if self.path.stack.is_empty() {
+            return None;
+        }
+        follow(self.root, &self.path.stack).value.as_ref()
+    }
+
+    /// The value of the current entry, `None` on the ghost position
+    pub fn value_mut(&mut self) -> Option<&mut V> {
+        if self.path.stack.is_empty() {
+            return None;
+        }
+        follow_mut(self.root, &self.path.stack).value.as_mut()
+    }
+
+    /// Move to the next entry, or from the ghost position to the first entry
+    pub fn move_next(&mut self) {
+        self.path.move_next(&Slotted(self.root))
+    }
+
+    /// Move to the previous entry, or from the ghost position to the last entry
+    pub fn move_prev(&mut self) {
+        self.path.move_prev(&Slotted(self.root))
+    }
+
+    /// Move to the first entry with a key greater than or equal to `key`
+    pub fn seek(&mut self, key: &[u8]) {
+        let key: Vec<u8> = K::symbols(key).collect();
+        self.path.seek(&Slotted(self.root), &key)
+    }
+
+    /// Replace the value of the current entry, returning the old one
+    pub fn replace(&mut self, value: V) -> Option<V> {
+        self.value_mut().map(|old| mem::replace(old, value))
+    }
+
+    /// Remove the current entry and move to the next one
+    pub fn remove_current(&mut self) -> Option<(K, V)> {
+        let key = self.path.key()?.to_vec();
+        let value = self.root.remove(&key)?;
+
+        // the nodes on the path may have merged or moved, so start over from
+        // the root, where seeking the removed key lands on the next entry
+        self.path.stack.clear();
+        self.path.seek(&Slotted(self.root), &key);
+
+        Some((K::new(key), value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::test_util;
+    use rand::prelude::*;
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+
+    fn tries() -> (ByteTrie<usize>, BTreeMap<Vec<u8>, usize>) {
+        let mut rng = test_util::rng();
+        let mut trie = ByteTrie::new();
+        let mut model = BTreeMap::new();
+
+        for i in 0..200 {
+            let key = test_util::colliding_key(&mut rng);
+            trie.insert(&key, i);
+            model.insert(key, i);
+        }
+
+        (trie, model)
+    }
+
+    #[test]
+    fn seek_finds_lower_bound() {
+        let (trie, model) = tries();
+        let mut rng = test_util::rng();
+        // targets also fall between the colliding bytes
+        let bytes = [0x00, 0x01, 0x3f, 0x40, 0x41, 0x80, 0xc0, 0xff];
+        let mut cursor = trie.cursor();
+
+        // reuses the path of the previous seek every time
+        for _ in 0..500 {
+            let len = rng.gen_range(0, 5);
+            let target: Vec<u8> = (0..len).map(|_| *bytes.choose(&mut rng).unwrap()).collect();
+
+            cursor.seek(&target);
+            let expected = model
+                .range::<[u8], _>((Bound::Included(&target[..]), Bound::Unbounded))
+                .next();
+            assert_eq!(
+                cursor.key_value().map(|(k, &v)| (k.to_bytes().unwrap(), v)),
+                expected.map(|(k, &v)| (k.clone(), v))
+            );
+        }
+    }
+
+    #[test]
+    fn steps_both_ways() {
+        let (mut trie, model) = tries();
+        let mut cursor = trie.cursor();
+
+        for (_, value) in model.iter() {
+            cursor.move_next();
+            assert_eq!(cursor.value(), Some(value));
+        }
+        cursor.move_next();
+        assert!(cursor.key().is_none());
+
+        for (_, value) in model.iter().rev() {
+            cursor.move_prev();
+            assert_eq!(cursor.value(), Some(value));
+        }
+        cursor.move_prev();
+        assert!(cursor.key().is_none());
+
+        // the mutable cursor walks the same path through bucket slots
+        let mut cursor = trie.cursor_mut();
+        for (key, value) in model.iter().rev() {
+            cursor.move_prev();
+            assert_eq!(cursor.key().and_then(|k| k.to_bytes()).as_ref(), Some(key));
+            assert_eq!(cursor.value(), Some(value));
+        }
+        cursor.move_prev();
+        assert!(cursor.value().is_none());
+        for (_, value) in model.iter() {
+            cursor.move_next();
+            assert_eq!(cursor.value(), Some(value));
+        }
+    }
+
+    #[test]
+    fn removes_and_replaces_current() {
+        let (mut trie, mut model) = tries();
+        let mut cursor = trie.cursor_mut();
+
+        cursor.seek(&[0x40]);
+        while let Some(key) = cursor.key() {
+            let key = key.to_bytes().unwrap();
+            if key[0] != 0x40 {
+                assert_eq!(cursor.replace(0), model.insert(key, 0));
+                cursor.move_next();
+            } else {
+                let (key, value) = cursor.remove_current().unwrap();
+                assert_eq!(model.remove(&key.to_bytes().unwrap()), Some(value));
+            }
+        }
+
+        let entries: Vec<_> = trie
+            .iter()
+            .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+            .collect();
+        assert_eq!(entries, model.into_iter().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,221 @@
+//! Changes between two versions of a trie, in key order.
+//!
+//! A diff walks both versions together like the set operations do, so a
+//! subtree only one version has is reported whole without looking at the
+//! other version again.  Persistent tries share every subtree that didn't
+//! change between versions, and a diff skips those by pointer, visiting only
+//! the paths that changed.  `Trie`s don't share nodes, so their diff still
+//! compares every key both versions have.  Hashing subtrees wouldn't help
+//! there without caching the hashes in the nodes, which every write would
+//! have to keep up to date.
+
+use crate::persistent::{PersistentNode, PersistentTrie};
+use crate::sets::{Found, Joint};
+use crate::tries::Trie;
+use crate::{AdaptiveNode, BytesKey};
+
+/// A key that changed between two versions
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change<'a, K, V> {
+    /// The key is only in the new version
+    Added(K, &'a V),
+    /// The key is only in the old version
+    Removed(K, &'a V),
+    /// The key has a different value in each version, old first
+    Changed(K, &'a V, &'a V),
+}
+
+impl<'a, K, V> Change<'a, K, V> {
+    /// The key that changed
+    pub fn key(&self) -> &K {
+        match self {
+            Change::Added(key, _) | Change::Removed(key, _) | Change::Changed(key, _, _) => key,
+        }
+    }
+}
+
+/// The walk of either kind of trie
+enum Walk<'a, K: BytesKey, V> {
+    Trie(Joint<'a, K, AdaptiveNode<K, V>, AdaptiveNode<K, V>>),
+    Persistent(Joint<'a, K, PersistentNode<K, V>, PersistentNode<K, V>>),
+}
+
+/// An iterator over the changes between two versions of a trie, in key order
+pub struct Diff<'a, K: BytesKey, V> {
+    walk: Walk<'a, K, V>,
+}
+
+impl<'a, K: BytesKey, V: PartialEq> Iterator for Diff<'a, K, V> {
+    type Item = Change<'a, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let found = match &mut self.walk {
+                Walk::Trie(joint) => joint.next()?,
+                Walk::Persistent(joint) => joint.next()?,
+            };
+            let change = match found {
+                (key, Found::Left(old)) => Change::Removed(K::new(key), old),
+                (key, Found::Right(new)) => Change::Added(K::new(key), new),
+                // shared values are equal without comparing them
+                (_, Found::Both(old, new)) if std::ptr::eq(old, new) || old == new => continue,
+                (key, Found::Both(old, new)) => Change::Changed(K::new(key), old, new),
+            };
+            return Some(change);
+        }
+    }
+}
+
+impl<K: BytesKey, T: PartialEq> Trie<K, T> {
+    /// The changes from this trie to `other`, in key order.
+    ///
+    /// This visits every key of both tries, so it takes O(n) even when they
+    /// barely differ.  Keep versions as a [`PersistentTrie`] for a diff that
+    /// skips the subtrees they share.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Diff<'a, K, T> {
+        let joint = Joint::new(Some(&self.root), Some(&other.root), true, true, true);
+        Diff {
+            walk: Walk::Trie(joint),
+        }
+    }
+}
+
+impl<K: BytesKey, T: PartialEq> PersistentTrie<K, T> {
+    /// The changes from this version to `other`, in key order.
+    ///
+    /// Subtrees both versions share are skipped without visiting them.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> Diff<'a, K, T> {
+        let joint = Joint::new(
+            self.root.as_deref(),
+            other.root.as_deref(),
+            true,
+            true,
+            true,
+        );
+        Diff {
+            walk: Walk::Persistent(joint.skip_shared()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::persistent::PersistentByteTrie;
+    use crate::prelude::*;
+    use crate::test_util::{self, colliding_key};
+    use rand::prelude::*;
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    type Model = BTreeMap<Vec<u8>, usize>;
+
+    /// The changes between two models, as diffs report them
+    fn expected(old: &Model, new: &Model) -> Vec<(Vec<u8>, Option<usize>, Option<usize>)> {
+        let mut keys: Vec<_> = old.keys().chain(new.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        keys.into_iter()
+            .map(|k| (k.clone(), old.get(k).copied(), new.get(k).copied()))
+            .filter(|(_, old, new)| old != new)
+            .collect()
+    }
+
+    fn changes<'a, K: BytesKey>(
+        diff: impl Iterator<Item = Change<'a, K, usize>>,
+    ) -> Vec<(Vec<u8>, Option<usize>, Option<usize>)> {
+        diff.map(|change| match change {
+            Change::Added(k, &new) => (k.to_bytes().unwrap(), None, Some(new)),
+            Change::Removed(k, &old) => (k.to_bytes().unwrap(), Some(old), None),
+            Change::Changed(k, &old, &new) => (k.to_bytes().unwrap(), Some(old), Some(new)),
+        })
+        .collect()
+    }
+
+    /// A model with some of its keys changed, removed and added
+    fn edit(rng: &mut StdRng, model: &Model, edits: usize) -> Model {
+        let mut model = model.clone();
+        for _ in 0..edits {
+            let key = colliding_key(rng);
+            if rng.gen_bool(0.3) {
+                model.remove(&key);
+            } else {
+                model.insert(key, rng.gen_range(0, 3));
+            }
+        }
+        model
+    }
+
+    fn check_diff<K: BytesKey>() {
+        let mut rng = test_util::rng();
+        for round in 0..50 {
+            let old = edit(&mut rng, &Model::new(), round * 3);
+            let new = edit(&mut rng, &old, round);
+            let trie =
+                |map: &Model| -> Trie<K, usize> { map.iter().map(|(k, &v)| (k, v)).collect() };
+
+            assert_eq!(changes(trie(&old).diff(&trie(&new))), expected(&old, &new));
+        }
+    }
+
+    #[test]
+    fn diffs_match_a_model() {
+        check_diff::<ByteKey>();
+        check_diff::<NibbleKey>();
+        check_diff::<BitKey>();
+        check_diff::<RadixKey<5>>();
+    }
+
+    #[test]
+    fn diffs_persistent_versions() {
+        let mut rng = test_util::rng();
+        let mut versions = vec![(PersistentByteTrie::new(), Model::new())];
+        for _ in 0..100 {
+            let (trie, model) = versions.last().unwrap();
+            let next = edit(&mut rng, model, 3);
+
+            let mut trie = trie.clone();
+            for key in model.keys().filter(|k| !next.contains_key(*k)) {
+                trie = trie.remove(key);
+            }
+            for (key, &value) in next.iter().filter(|(k, v)| model.get(*k) != Some(v)) {
+                trie = trie.insert(key, value);
+            }
+            versions.push((trie, next));
+        }
+
+        for _ in 0..100 {
+            let (old, old_model) = versions.choose(&mut rng).unwrap();
+            let (new, new_model) = versions.choose(&mut rng).unwrap();
+            assert_eq!(changes(old.diff(new)), expected(old_model, new_model));
+        }
+    }
+
+    #[test]
+    fn skips_shared_subtrees() {
+        static COMPARED: AtomicUsize = AtomicUsize::new(0);
+
+        struct Counted(usize);
+        impl PartialEq for Counted {
+            fn eq(&self, other: &Self) -> bool {
+                COMPARED.fetch_add(1, Ordering::SeqCst);
+                self.0 == other.0
+            }
+        }
+
+        let mut trie = PersistentByteTrie::new();
+        for i in 0..1000u32 {
+            trie = trie.insert(&i.to_be_bytes(), Counted(0));
+        }
+        let next = trie
+            .insert(&7u32.to_be_bytes(), Counted(1))
+            .insert(&[0xff], Counted(2));
+
+        let diff: Vec<_> = trie
+            .diff(&next)
+            .map(|change| change.key().to_bytes())
+            .collect();
+        assert_eq!(diff, [Some(7u32.to_be_bytes().to_vec()), Some(vec![0xff])]);
+        // only the changed value gets compared, everything else is shared
+        assert_eq!(COMPARED.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,270 @@
+//! Graphviz DOT export of the internal node structure.
+//!
+//! Each `AdaptiveNode` is drawn as a box labeled with its key fragment, with a
+//! double border when it holds a value.  Child buckets are drawn as records
+//! showing their size and only their occupied slots, since drawing 256 empty
+//! slots doesn't help anyone.
+//!
+//! A byte prefix doesn't always end on a whole symbol.  Only the symbols it
+//! fully covers pick the starting node, and the symbol it ends in the middle
+//! of can be anything from the prefix padded with zero bits to the prefix
+//! padded with one bits, the same range `iter_prefix` reads.  Children of the
+//! starting node outside that range are left out.
+
+use crate::child::Child;
+use crate::tries::Trie;
+use crate::{AdaptiveNode, BytesKey};
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+/// Options to keep the output of large tries usable.
+#[derive(Debug, Default, Clone)]
+pub struct DotOptions {
+    /// Only draw nodes this many levels below the starting node.
+    ///
+    /// Nodes with children past the limit are drawn with a dashed border.
+    pub max_depth: Option<usize>,
+
+    /// Start drawing from the node containing all keys with this byte prefix.
+    pub prefix: Vec<u8>,
+}
+
+impl DotOptions {
+    /// Draw the whole trie
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only draw nodes up to `depth` levels below the starting node
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Start drawing from the node that contains all keys starting with `prefix`
+    pub fn prefix(mut self, prefix: &[u8]) -> Self {
+        self.prefix = prefix.to_vec();
+        self
+    }
+}
+
+impl<K: BytesKey, V> AdaptiveNode<K, V> {
+    /// Write the node and its descendants as a Graphviz DOT graph.
+    ///
+    /// `options.prefix` is ignored here as it is already in key representation
+    /// once we are at a node, use the trie's `write_dot` to start at a prefix.
+    pub fn write_dot<W: Write>(&self, w: W, options: &DotOptions) -> io::Result<()> {
+        self.write_dot_within(w, options, None)
+    }
+
+    /// Write the node, leaving out its children whose first symbol is outside `first`
+    fn write_dot_within<W: Write>(
+        &self,
+        mut w: W,
+        options: &DotOptions,
+        first: Option<&RangeInclusive<u8>>,
+    ) -> io::Result<()> {
+        writeln!(w, "digraph trie {{")?;
+        writeln!(w, "    node [fontname=\"monospace\"];")?;
+        DotWriter {
+            w: &mut w,
+            max_depth: options.max_depth,
+            next_id: 0,
+        }
+        .node(self, 0, first)?;
+        writeln!(w, "}}")
+    }
+
+    /// Render the node and its descendants as a Graphviz DOT graph
+    pub fn to_dot(&self, options: &DotOptions) -> String {
+        let mut out = Vec::new();
+        self.write_dot(&mut out, options)
+            .expect("writing to a Vec can't fail");
+        String::from_utf8(out).expect("DOT output is always utf8")
+    }
+}
+
+struct DotWriter<'w, W: Write> {
+    w: &'w mut W,
+    max_depth: Option<usize>,
+    next_id: usize,
+}
+
+impl<'w, W: Write> DotWriter<'w, W> {
+    fn id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Write a node and everything below it, returning the node's id
+    fn node<K: BytesKey, V>(
+        &mut self,
+        node: &AdaptiveNode<K, V>,
+        depth: usize,
+        first: Option<&RangeInclusive<u8>>,
+    ) -> io::Result<usize> {
+        let id = self.id();
+        let key = node.key.to_string();
+        let key = if key.is_empty() { "ε" } else { &key };
+        let truncated = node.child.is_some() && self.max_depth.is_some_and(|max| depth >= max);
+
+        write!(self.w, "    n{} [shape=box, label=\"{}", id, key)?;
+        if node.value.is_some() {
+            write!(self.w, "\\nvalue\", peripheries=2")?;
+        } else {
+            write!(self.w, "\"")?;
+        }
+        if truncated {
+            write!(self.w, ", style=dashed")?;
+        }
+        writeln!(self.w, "];")?;
+
+        if let (Some(child), false) = (&node.child, truncated) {
+            let bucket = self.child(child, depth, first)?;
+            writeln!(self.w, "    n{} -> b{};", id, bucket)?;
+        }
+
+        Ok(id)
+    }
+
+    /// Write a child bucket and its occupied slots, returning the bucket's id.
+    ///
+    /// Nodes with a first symbol outside `first` are left out.
+    fn child<K: BytesKey, V>(
+        &mut self,
+        child: &Child<K, V>,
+        depth: usize,
+        first: Option<&RangeInclusive<u8>>,
+    ) -> io::Result<usize> {
+        let id = self.id();
+        let occupied: Vec<_> = child
+            .get()
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, node)| node.as_ref().map(|node| (slot, node)))
+            .filter(|(_, node)| match first {
+                Some(first) if node.key.symbol_len() > 0 => first.contains(&node.key.symbol(0)),
+                _ => true,
+            })
+            .collect();
+
+        write!(
+            self.w,
+            "    b{} [shape=record, label=\"{{size {}|{{",
+            id,
+            child.size()
+        )?;
+        for (i, (slot, _)) in occupied.iter().enumerate() {
+            if i > 0 {
+                write!(self.w, "|")?;
+            }
+            write!(self.w, "<s{0}> {0}", slot)?;
+        }
+        writeln!(self.w, "}}}}\"];")?;
+
+        for (slot, node) in occupied {
+            // empty keyed nodes hold collided slots of this same bucket
+            let first = first.filter(|_| node.key.symbol_len() == 0);
+            let node = self.node(node, depth + 1, first)?;
+            writeln!(self.w, "    b{}:s{} -> n{};", id, slot, node)?;
+        }
+
+        Ok(id)
+    }
+}
+
+impl<K: BytesKey, T> Trie<K, T> {
+    /// Write the trie structure as a Graphviz DOT graph.
+    ///
+    /// Writes an empty graph if no keys start with `options.prefix`.
+    pub fn write_dot<W: Write>(&self, mut w: W, options: &DotOptions) -> io::Result<()> {
+        let prefix = &options.prefix;
+        let symbols = K::symbols(prefix);
+        let covered = symbols.clone().covered();
+        let at = covered.len();
+
+        let partial = symbols.get(at).map(|low| {
+            let mut ones = prefix.clone();
+            ones.push(0xff);
+            low..=K::symbols(&ones)
+                .get(at)
+                .expect("one more byte is more symbols")
+        });
+
+        match self.root.prefix_node(covered) {
+            Some((node, end)) if end < node.key.symbol_len() => match partial {
+                Some(partial) if !partial.contains(&node.key.symbol(end)) => {
+                    writeln!(w, "digraph trie {{\n}}")
+                }
+                _ => node.write_dot(w, options),
+            },
+            Some((node, _)) => node.write_dot_within(w, options, partial.as_ref()),
+            None => writeln!(w, "digraph trie {{\n}}"),
+        }
+    }
+
+    /// Render the trie structure as a Graphviz DOT graph
+    pub fn to_dot(&self, options: &DotOptions) -> String {
+        let mut out = Vec::new();
+        self.write_dot(&mut out, options)
+            .expect("writing to a Vec can't fail");
+        String::from_utf8(out).expect("DOT output is always utf8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::RadixKey;
+    use crate::tries::ByteTrie;
+    use crate::BytesTrie;
+
+    #[test]
+    fn draws_collided_bucket() {
+        let mut trie = ByteTrie::new();
+        trie.insert(&[0x00, 0x01], ());
+        trie.insert(&[0x80, 0x01], ());
+        trie.insert(&[0x00, 0x02], ());
+
+        let dot = trie.to_dot(&DotOptions::new());
+        assert!(dot.starts_with("digraph trie {"));
+        assert!(dot.contains("label=\"00\"]"));
+        assert!(dot.contains("label=\"02\\nvalue\", peripheries=2]"));
+
+        let shallow = trie.to_dot(&DotOptions::new().prefix(&[0x00]).max_depth(0));
+        assert!(shallow.contains("label=\"00\", style=dashed]"));
+        assert!(!shallow.contains("->"));
+    }
+
+    #[test]
+    fn prefixes_end_inside_symbols() {
+        // 5 bit symbols: [0, 0, 0, 16], [0, 3, 31, 16] and [0, 4]
+        let mut trie: Trie<RadixKey<5>, _> = Trie::new();
+        trie.insert(&[0x00, 0x01], ());
+        trie.insert(&[0x00, 0xff], ());
+        trie.insert(&[0x01], ());
+
+        // a second symbol of 000xx
+        let dot = trie.to_dot(&DotOptions::new().prefix(&[0x00]));
+        assert_eq!(trie.iter_prefix(&[0x00]).count(), 2);
+        assert!(dot.contains("label=\"000010\\nvalue\""));
+        assert!(dot.contains("label=\"031f10\\nvalue\""));
+        assert!(!dot.contains("label=\"04"));
+
+        // a second symbol of 001xx
+        let dot = trie.to_dot(&DotOptions::new().prefix(&[0x01]));
+        assert!(dot.contains("label=\"04\\nvalue\""));
+        assert!(!dot.contains("label=\"000010"));
+
+        // the prefix ends inside the symbols of a single node
+        let mut single: Trie<RadixKey<5>, _> = Trie::new();
+        single.insert(&[0x00, 0x01], ());
+        assert!(single
+            .to_dot(&DotOptions::new().prefix(&[0x00]))
+            .contains("label=\"00000010"));
+        assert_eq!(
+            single.to_dot(&DotOptions::new().prefix(&[0x01])),
+            "digraph trie {\n}\n"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! Entries of a key in any trie, for updating a value in place.
+//!
+//! Entries only use the lookups of `BytesTrieExt`, so they work for every
+//! trie implementing it.  That costs a second lookup when an entry turns a
+//! key into a reference to its value, in exchange for not tying entries to
+//! the node layout.
+
+use crate::BytesTrieExt;
+use std::marker::PhantomData;
+
+/// The entry of a key, which is either occupied or vacant
+pub enum Entry<'a, Tr, T> {
+    /// The trie has a value for the key
+    Occupied(OccupiedEntry<'a, Tr, T>),
+    /// The trie has no value for the key
+    Vacant(VacantEntry<'a, Tr, T>),
+}
+
+/// The entry of a key that has a value
+pub struct OccupiedEntry<'a, Tr, T> {
+    trie: &'a mut Tr,
+    key: Vec<u8>,
+    value: PhantomData<T>,
+}
+
+/// The entry of a key without a value
+pub struct VacantEntry<'a, Tr, T> {
+    trie: &'a mut Tr,
+    key: Vec<u8>,
+    value: PhantomData<T>,
+}
+
+impl<'a, Tr: BytesTrieExt<T>, T> Entry<'a, Tr, T> {
+    pub(crate) fn new(trie: &'a mut Tr, key: &[u8]) -> Self {
+        let key = key.to_vec();
+        if trie.contains_key(&key) {
+            Entry::Occupied(OccupiedEntry {
+                trie,
+                key,
+                value: PhantomData,
+            })
+        } else {
+            Entry::Vacant(VacantEntry {
+                trie,
+                key,
+                value: PhantomData,
+            })
+        }
+    }
+
+    /// The key of the entry
+    pub fn key(&self) -> &[u8] {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// The value of the key, inserting `default` if it has none
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        self.or_insert_with(|| default)
+    }
+
+    /// The value of the key, inserting the result of `default` if it has none
+    pub fn or_insert_with<F: FnOnce() -> T>(self, default: F) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Modify the value of the key if it has one
+    pub fn and_modify<F: FnOnce(&mut T)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, Tr: BytesTrieExt<T>, T: Default> Entry<'a, Tr, T> {
+    /// The value of the key, inserting the default value if it has none
+    pub fn or_default(self) -> &'a mut T {
+        self.or_insert_with(T::default)
+    }
+}
+
+impl<'a, Tr: BytesTrieExt<T>, T> OccupiedEntry<'a, Tr, T> {
+    /// The key of the entry
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// A reference to the value
+    pub fn get(&self) -> &T {
+        self.trie
+            .get(&self.key)
+            .expect("occupied entries have a value")
+    }
+
+    /// A mutable reference to the value
+    pub fn get_mut(&mut self) -> &mut T {
+        self.trie
+            .get_mut(&self.key)
+            .expect("occupied entries have a value")
+    }
+
+    /// Turn the entry into a mutable reference to the value
+    pub fn into_mut(self) -> &'a mut T {
+        self.trie
+            .get_mut(&self.key)
+            .expect("occupied entries have a value")
+    }
+
+    /// Replace the value, returning the old one
+    pub fn insert(&mut self, value: T) -> T {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// Remove the key from the trie, returning its value
+    pub fn remove(self) -> T {
+        self.trie
+            .remove(&self.key)
+            .expect("occupied entries have a value")
+    }
+}
+
+impl<'a, Tr: BytesTrieExt<T>, T> VacantEntry<'a, Tr, T> {
+    /// The key of the entry
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Insert a value for the key, returning a mutable reference to it
+    pub fn insert(self, value: T) -> &'a mut T {
+        self.trie.insert(&self.key, value);
+        self.trie
+            .get_mut(&self.key)
+            .expect("the value was just inserted")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use std::collections::BTreeMap;
+
+    /// Count words and look some up, without knowing the key representation
+    fn word_counts<Tr: BytesTrieExt<usize>>() -> Tr {
+        let mut trie = Tr::new();
+        for word in "the cat and the hat and the bat".split(' ') {
+            *trie.entry(word.as_bytes()).or_default() += 1;
+        }
+
+        trie.entry(b"cat").and_modify(|count| *count += 10);
+        if let Entry::Occupied(entry) = trie.entry(b"bat") {
+            assert_eq!(entry.remove(), 1);
+        }
+        assert_eq!(*trie.entry(b"ant").or_insert(7), 7);
+        trie
+    }
+
+    fn check<Tr: BytesTrieExt<usize>>() {
+        let trie = word_counts::<Tr>();
+        assert_eq!(trie.len(), 5);
+        assert!(!trie.is_empty());
+        assert_eq!(trie.get(b"the"), Some(&3));
+        assert!(!trie.contains_key(b"bat"));
+
+        let counts: Vec<_> = trie.iter().map(|(_, &count)| count).collect();
+        assert_eq!(counts, [2, 7, 11, 1, 3]);
+        let counts: Vec<_> = trie.iter_prefix(b"a").map(|(_, &count)| count).collect();
+        assert_eq!(counts, [2, 7]);
+    }
+
+    /// A map outside of the tries, iterating with an iterator of its own
+    struct SortedMap<T>(BTreeMap<Vec<u8>, T>);
+
+    impl<T> BytesTrie<T> for SortedMap<T> {
+        fn new() -> Self {
+            SortedMap(BTreeMap::new())
+        }
+
+        fn insert(&mut self, key: &[u8], value: T) {
+            self.0.insert(key.to_vec(), value);
+        }
+    }
+
+    impl<T> BytesTrieExt<T> for SortedMap<T> {
+        type Key = ByteKey;
+        type Iter<'a>
+            = Box<dyn Iterator<Item = (ByteKey, &'a T)> + 'a>
+        where
+            T: 'a;
+
+        fn get(&self, key: &[u8]) -> Option<&T> {
+            self.0.get(key)
+        }
+
+        fn get_mut(&mut self, key: &[u8]) -> Option<&mut T> {
+            self.0.get_mut(key)
+        }
+
+        fn remove(&mut self, key: &[u8]) -> Option<T> {
+            self.0.remove(key)
+        }
+
+        fn iter(&self) -> Self::Iter<'_> {
+            self.iter_prefix(&[])
+        }
+
+        fn iter_prefix(&self, prefix: &[u8]) -> Self::Iter<'_> {
+            let prefix = prefix.to_vec();
+            let entries = self.0.range(prefix.clone()..);
+            Box::new(
+                entries
+                    .take_while(move |(key, _)| key.starts_with(&prefix))
+                    .map(|(key, value)| (ByteKey::from_bytes(key), value)),
+            )
+        }
+    }
+
+    #[test]
+    fn works_for_every_representation() {
+        check::<ByteTrie<usize>>();
+        check::<NibbleTrie<usize>>();
+        check::<BitTrie<usize>>();
+        check::<Trie<RadixKey<5>, usize>>();
+        check::<SortedMap<usize>>();
+    }
+
+    #[test]
+    fn iter_prefix_handles_max_bytes() {
+        let trie: ByteTrie<_> = vec![
+            (&[0x01, 0xff][..], 0),
+            (&[0x01, 0xff, 0xff], 1),
+            (&[0x02], 2),
+            (&[0xff], 3),
+        ]
+        .into_iter()
+        .collect();
+
+        let values =
+            |prefix: &[u8]| -> Vec<_> { trie.iter_prefix(prefix).map(|(_, &v)| v).collect() };
+        assert_eq!(values(&[0x01, 0xff]), [0, 1]);
+        assert_eq!(values(&[0xff]), [3]);
+        assert_eq!(values(&[]), [0, 1, 2, 3]);
+        assert!(values(&[0x03]).is_empty());
+    }
+}
@@ -0,0 +1,389 @@
+//! Tries of keys that all have the same length, like SHA-1 or SHA-256 hashes.
+//!
+//! With every key exactly `N` bytes long, no key is a prefix of another, so
+//! only leaves hold values.  A leaf keeps its whole key in a `[u8; N]` and a
+//! branch only records the byte position where the keys below it first
+//! differ.  Nodes keep no key fragments or lengths, a lookup follows the
+//! branch positions down and compares the whole key once at the leaf.
+//!
+//! Branch buckets use the same sizes and `byte % size` slots as
+//! `AdaptiveNode`, but a bucket grows to the next size when two bytes collide
+//! instead of adding an empty keyed node.
+
+use crate::child::MAX_CHILD_SIZE;
+use crate::nodes::{next_size, NO_CHILD};
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+/// A trie of SHA-1 object ids
+pub type Sha1Trie<V> = FixedTrie<V, 20>;
+
+/// A trie of SHA-256 object ids
+pub type Sha256Trie<V> = FixedTrie<V, 32>;
+
+/// A key didn't have the length of a fixed width trie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyLengthError {
+    /// The key length of the trie
+    pub expected: usize,
+    /// The length of the key that was given
+    pub found: usize,
+}
+
+impl fmt::Display for KeyLengthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "expected a key of {} bytes, found {} bytes",
+            self.expected, self.found
+        )
+    }
+}
+
+impl Error for KeyLengthError {}
+
+type Bucket<V, const N: usize> = Box<[Option<Node<V, N>>]>;
+
+enum Node<V, const N: usize> {
+    /// The only key below this point, kept whole
+    Leaf([u8; N], V),
+    /// Every key below shares the bytes before `index` and differs at it
+    Branch {
+        index: usize,
+        children: Bucket<V, N>,
+    },
+}
+
+impl<V, const N: usize> Node<V, N> {
+    /// Any key below the node, they all share the bytes before a branch index
+    fn any_key(&self) -> &[u8; N] {
+        match self {
+            Node::Leaf(key, _) => key,
+            Node::Branch { children, .. } => children
+                .iter()
+                .flatten()
+                .next()
+                .expect("branches have at least two children")
+                .any_key(),
+        }
+    }
+
+    /// The node `key` leads to, following its bytes down the branches
+    fn descend(&self, key: &[u8; N]) -> &Self {
+        let mut node = self;
+        while let Node::Branch { index, children } = node {
+            match &children[key[*index] as usize % children.len()] {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+        node
+    }
+
+    fn get(&self, key: &[u8; N]) -> Option<&V> {
+        match self.descend(key) {
+            Node::Leaf(leaf, value) if leaf == key => Some(value),
+            _ => None,
+        }
+    }
+
+    fn get_mut(&mut self, key: &[u8; N]) -> Option<&mut V> {
+        match self {
+            Node::Leaf(leaf, value) if leaf == key => Some(value),
+            Node::Leaf(..) => None,
+            Node::Branch { index, children } => {
+                let slot = key[*index] as usize % children.len();
+                children[slot].as_mut()?.get_mut(key)
+            }
+        }
+    }
+}
+
+/// The smallest bucket size larger than `size` with a slot for every byte
+fn unique_size(mut size: usize, bytes: &[u8]) -> usize {
+    loop {
+        size = next_size(size);
+        let mut taken = [false; MAX_CHILD_SIZE];
+        let unique = bytes
+            .iter()
+            .all(|&byte| !std::mem::replace(&mut taken[byte as usize % size], true));
+        if unique || size == MAX_CHILD_SIZE {
+            return size;
+        }
+    }
+}
+
+/// Place nodes in a bucket of `size` by their byte at `index`
+fn bucket<V, const N: usize>(nodes: Vec<Node<V, N>>, index: usize, size: usize) -> Bucket<V, N> {
+    let mut bucket: Vec<_> = (0..size).map(|_| None).collect();
+    for node in nodes {
+        let slot = node.any_key()[index] as usize % size;
+        bucket[slot] = Some(node);
+    }
+    bucket.into_boxed_slice()
+}
+
+/// Insert a key that first differs from the keys already below `slot` at `index`
+fn insert<V, const N: usize>(slot: &mut Option<Node<V, N>>, index: usize, key: [u8; N], value: V) {
+    match slot {
+        // every key below shares the new key's byte at `at`
+        Some(Node::Branch {
+            index: at,
+            children,
+        }) if *at < index => {
+            let slot = key[*at] as usize % children.len();
+            insert(&mut children[slot], index, key, value)
+        }
+        Some(Node::Branch {
+            index: at,
+            children,
+        }) if *at == index => {
+            let leaf = Node::Leaf(key, value);
+            let slot = key[index] as usize % children.len();
+            if children[slot].is_none() {
+                children[slot] = Some(leaf);
+                return;
+            }
+
+            let mut nodes: Vec<_> = children.iter_mut().filter_map(Option::take).collect();
+            nodes.push(leaf);
+            let bytes: Vec<_> = nodes.iter().map(|node| node.any_key()[index]).collect();
+            *children = bucket(nodes, index, unique_size(children.len(), &bytes));
+        }
+        _ => {
+            let below = slot.take().expect("the key differs from a key below");
+            let bytes = [below.any_key()[index], key[index]];
+            let nodes = vec![below, Node::Leaf(key, value)];
+            *slot = Some(Node::Branch {
+                index,
+                children: bucket(nodes, index, unique_size(NO_CHILD, &bytes)),
+            });
+        }
+    }
+}
+
+/// Remove a key below `slot`, replacing branches left with one child by it
+fn remove<V, const N: usize>(slot: &mut Option<Node<V, N>>, key: &[u8; N]) -> Option<V> {
+    match slot.as_mut()? {
+        Node::Leaf(leaf, _) => {
+            if leaf != key {
+                return None;
+            }
+
+            match slot.take() {
+                Some(Node::Leaf(_, value)) => Some(value),
+                _ => unreachable!("the slot holds a leaf"),
+            }
+        }
+        Node::Branch { index, children } => {
+            let child = key[*index] as usize % children.len();
+            let value = remove(&mut children[child], key)?;
+
+            if children.iter().flatten().count() == 1 {
+                *slot = children.iter_mut().find_map(Option::take);
+            }
+            Some(value)
+        }
+    }
+}
+
+/// A trie of keys that are all exactly `N` bytes long.
+///
+/// Keys of any other length are rejected with a [`KeyLengthError`].
+pub struct FixedTrie<V, const N: usize> {
+    root: Option<Node<V, N>>,
+    len: usize,
+}
+
+impl<V, const N: usize> FixedTrie<V, N> {
+    /// Create an empty trie
+    pub fn new() -> Self {
+        Self { root: None, len: 0 }
+    }
+
+    /// Check that `key` is `N` bytes long
+    fn key(key: &[u8]) -> Result<&[u8; N], KeyLengthError> {
+        key.try_into().map_err(|_| KeyLengthError {
+            expected: N,
+            found: key.len(),
+        })
+    }
+
+    /// Insert a key, replacing the value of an existing one
+    pub fn insert(&mut self, key: &[u8], value: V) -> Result<(), KeyLengthError> {
+        let key = *Self::key(key)?;
+        let root = match &mut self.root {
+            Some(root) => root,
+            None => {
+                self.root = Some(Node::Leaf(key, value));
+                self.len = 1;
+                return Ok(());
+            }
+        };
+
+        // the first byte the key differs from its closest key at
+        let closest = root.descend(&key).any_key();
+        match closest.iter().zip(&key).position(|(lhs, rhs)| lhs != rhs) {
+            Some(index) => {
+                insert(&mut self.root, index, key, value);
+                self.len += 1;
+            }
+            None => *root.get_mut(&key).expect("the closest key is the key") = value,
+        }
+        Ok(())
+    }
+
+    /// Get the value of a key
+    pub fn get(&self, key: &[u8]) -> Result<Option<&V>, KeyLengthError> {
+        let key = Self::key(key)?;
+        Ok(self.root.as_ref().and_then(|root| root.get(key)))
+    }
+
+    /// Get a mutable reference to the value of a key
+    pub fn get_mut(&mut self, key: &[u8]) -> Result<Option<&mut V>, KeyLengthError> {
+        let key = Self::key(key)?;
+        Ok(self.root.as_mut().and_then(|root| root.get_mut(key)))
+    }
+
+    /// If the trie holds a key
+    pub fn contains_key(&self, key: &[u8]) -> Result<bool, KeyLengthError> {
+        self.get(key).map(|value| value.is_some())
+    }
+
+    /// Remove a key, returning its value
+    pub fn remove(&mut self, key: &[u8]) -> Result<Option<V>, KeyLengthError> {
+        let key = Self::key(key)?;
+        let value = remove(&mut self.root, key);
+        if value.is_some() {
+            self.len -= 1;
+        }
+        Ok(value)
+    }
+
+    /// The number of keys in the trie
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// If the trie has no keys
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterate over the entries of the trie in key order
+    pub fn iter(&self) -> Iter<'_, V, N> {
+        Iter {
+            nodes: self.root.iter().collect(),
+            len: self.len,
+        }
+    }
+}
+
+impl<V, const N: usize> Default for FixedTrie<V, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, V, const N: usize> IntoIterator for &'a FixedTrie<V, N> {
+    type Item = (&'a [u8; N], &'a V);
+    type IntoIter = Iter<'a, V, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the entries of a [`FixedTrie`] in key order
+pub struct Iter<'a, V, const N: usize> {
+    /// Nodes still to visit, the next one last
+    nodes: Vec<&'a Node<V, N>>,
+    len: usize,
+}
+
+impl<'a, V, const N: usize> Iterator for Iter<'a, V, N> {
+    type Item = (&'a [u8; N], &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.nodes.pop()? {
+                Node::Leaf(key, value) => {
+                    self.len -= 1;
+                    return Some((key, value));
+                }
+                Node::Branch { index, children } => {
+                    let start = self.nodes.len();
+                    self.nodes.extend(children.iter().flatten());
+                    self.nodes[start..]
+                        .sort_by_key(|node| std::cmp::Reverse(node.any_key()[*index]));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, V, const N: usize> ExactSizeIterator for Iter<'a, V, N> {}
+
+impl<'a, V, const N: usize> std::iter::FusedIterator for Iter<'a, V, N> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use rand::prelude::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn matches_btree() {
+        let mut rng = test_util::rng();
+        // few distinct bytes, so buckets collide and branches sit at every index
+        let bytes = [0x00, 0x01, 0x40, 0x80, 0xc0, 0xc1, 0xff];
+        let mut trie = FixedTrie::<usize, 4>::new();
+        let mut model = BTreeMap::new();
+
+        for i in 0..2000 {
+            let key: [u8; 4] = [(); 4].map(|_| *bytes.choose(&mut rng).unwrap());
+            if rng.gen_ratio(1, 3) {
+                assert_eq!(trie.remove(&key), Ok(model.remove(&key)));
+            } else {
+                trie.insert(&key, i).unwrap();
+                model.insert(key, i);
+            }
+            assert_eq!(trie.len(), model.len());
+        }
+
+        for (key, value) in &model {
+            assert_eq!(trie.get(key), Ok(Some(value)));
+        }
+        let entries: Vec<_> = trie.iter().map(|(k, &v)| (*k, v)).collect();
+        let expected: Vec<_> = model.into_iter().collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn rejects_keys_of_the_wrong_length() {
+        let mut trie = Sha1Trie::new();
+        let error = KeyLengthError {
+            expected: 20,
+            found: 19,
+        };
+        assert_eq!(trie.insert(&[0; 19], ()), Err(error.clone()));
+        assert_eq!(trie.get(&[0; 19]), Err(error));
+        assert_eq!(
+            trie.remove(&[0; 32]),
+            Err(KeyLengthError {
+                expected: 20,
+                found: 32
+            })
+        );
+
+        trie.insert(&[0; 20], ()).unwrap();
+        assert_eq!(trie.contains_key(&[0; 20]), Ok(true));
+        assert_eq!(trie.len(), 1);
+    }
+}
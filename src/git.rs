@@ -0,0 +1,569 @@
+//! Importing object ids from git repositories.
+//!
+//! Pack indices (`.idx` version 2) list the id of every object in a pack in
+//! sorted order, followed by the offset of each object in the pack.  Both SHA-1
+//! and SHA-256 repositories are supported.  The object format isn't recorded
+//! in the index, so it is passed in like for loose objects, and the file size
+//! has to fit it.  The checksums at the end of the index are not verified.
+//!
+//! Loose objects are stored one per file, under a fanout directory named
+//! after the first byte of the object id, with the rest of the id as the file
+//! name.
+
+use crate::hex::{self, HexError};
+use crate::sorted::UnsortedError;
+use crate::tries::ByteTrie;
+use crate::BytesTrie;
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use std::{error, fmt, fs, io};
+
+/// Every version 2 pack index starts with this, version 1 has no signature
+const IDX_SIGNATURE: &[u8] = b"\xfftOc";
+
+/// The signature, the version and the fanout table
+const IDX_HEADER: usize = 8 + 256 * 4;
+
+/// Offsets with this bit set are positions in the large offset table
+const LARGE_OFFSET: u32 = 0x8000_0000;
+
+/// Git writes loose objects to temporary files in the fanout directory first
+const TEMPORARY_OBJECT: &str = "tmp_obj_";
+
+/// The hash function of a repository, deciding the length of object ids
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectFormat {
+    /// 20 byte object ids
+    Sha1,
+    /// 32 byte object ids
+    Sha256,
+}
+
+impl ObjectFormat {
+    /// The length of an object id in bytes
+    pub fn oid_len(self) -> usize {
+        match self {
+            ObjectFormat::Sha1 => 20,
+            ObjectFormat::Sha256 => 32,
+        }
+    }
+}
+
+/// Errors from reading git object ids
+#[derive(Debug)]
+pub enum Error {
+    /// Reading a file failed
+    Io(io::Error),
+    /// The file doesn't start with the pack index signature
+    Signature,
+    /// The pack index is a version other than 2
+    Version(u32),
+    /// The fanout table counts go down
+    Fanout,
+    /// The file size doesn't fit the number of objects in the object format
+    Length(usize),
+    /// The object at this position has the same id as the one before it
+    Duplicate(usize),
+    /// The object at this position has an offset past the large offset table
+    Offset(usize),
+    /// The object ids are not sorted
+    Unsorted(UnsortedError),
+    /// A loose object file or fanout directory name is not part of an object id
+    Name(PathBuf),
+    /// A loose object file or fanout directory name is not hex
+    Hex(PathBuf, HexError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Signature => write!(f, "not a version 2 pack index"),
+            Error::Version(version) => write!(f, "unsupported pack index version {}", version),
+            Error::Fanout => write!(f, "pack index fanout table is not sorted"),
+            Error::Length(len) => write!(f, "pack index has an invalid length of {}", len),
+            Error::Duplicate(index) => write!(f, "object {} is in the pack index twice", index),
+            Error::Offset(index) => write!(f, "object {} has an invalid large offset", index),
+            Error::Unsorted(err) => write!(f, "pack index object ids: {}", err),
+            Error::Name(path) => write!(f, "malformed loose object name {}", path.display()),
+            Error::Hex(path, err) => {
+                write!(f, "loose object name {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Unsorted(err) => Some(err),
+            Error::Hex(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// The tables of a version 2 pack index, borrowed from its bytes
+#[derive(Debug, Clone)]
+pub struct PackIndex<'a> {
+    format: ObjectFormat,
+    oids: &'a [u8],
+    offsets: &'a [u8],
+    large: &'a [u8],
+}
+
+impl<'a> PackIndex<'a> {
+    /// Parse the tables of a pack index of `format` object ids, checking they
+    /// fit the file size
+    pub fn parse(bytes: &'a [u8], format: ObjectFormat) -> Result<Self, Error> {
+        if bytes.len() < IDX_HEADER {
+            return Err(Error::Length(bytes.len()));
+        }
+
+        if !bytes.starts_with(IDX_SIGNATURE) {
+            return Err(Error::Signature);
+        }
+
+        let version = be_u32(&bytes[4..]);
+        if version != 2 {
+            return Err(Error::Version(version));
+        }
+
+        // the last fanout entry counts every object
+        let mut count = 0;
+        for entry in bytes[8..IDX_HEADER].chunks(4) {
+            let next = be_u32(entry);
+            if next < count {
+                return Err(Error::Fanout);
+            }
+            count = next;
+        }
+
+        let index =
+            Self::with_format(bytes, count as usize, format).ok_or(Error::Length(bytes.len()))?;
+
+        // a trie would silently keep only one of them
+        let mut oids = index.oids.chunks(format.oid_len());
+        let mut previous = oids.next();
+        for (position, oid) in oids.enumerate() {
+            if previous == Some(oid) {
+                return Err(Error::Duplicate(position + 1));
+            }
+            previous = Some(oid);
+        }
+
+        let large_len = index.large.len() / 8;
+        for (position, offset) in index.offsets.chunks(4).enumerate() {
+            let offset = be_u32(offset);
+            if offset & LARGE_OFFSET != 0 && (offset & !LARGE_OFFSET) as usize >= large_len {
+                return Err(Error::Offset(position));
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Split up the tables if the file size fits `format`
+    fn with_format(bytes: &'a [u8], count: usize, format: ObjectFormat) -> Option<Self> {
+        // the count comes from the file, so it can overflow on 32 bit targets
+        let oid_len = format.oid_len();
+        let oids_end = count.checked_mul(oid_len)?.checked_add(IDX_HEADER)?;
+        // skip the CRC32 of every object
+        let table_len = count.checked_mul(4)?;
+        let offsets_start = oids_end.checked_add(table_len)?;
+        let offsets_end = offsets_start.checked_add(table_len)?;
+        // the pack and index checksums end the file
+        let large_end = bytes.len().checked_sub(2 * oid_len)?;
+
+        let offsets = bytes.get(offsets_start..offsets_end)?;
+        let large = bytes.get(offsets_end..large_end)?;
+        let large_count = offsets
+            .chunks(4)
+            .filter(|offset| be_u32(offset) & LARGE_OFFSET != 0)
+            .count();
+
+        if large.len() != large_count * 8 {
+            return None;
+        }
+
+        Some(Self {
+            format,
+            oids: &bytes[IDX_HEADER..oids_end],
+            offsets,
+            large,
+        })
+    }
+
+    /// The object format of the repository the pack belongs to
+    pub fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    /// The number of objects in the pack
+    pub fn len(&self) -> usize {
+        self.offsets.len() / 4
+    }
+
+    /// If the pack has no objects
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// The object ids and their offsets in the pack, in object id order
+    pub fn iter(&self) -> impl Iterator<Item = (&'a [u8], u64)> + 'a {
+        let large = self.large;
+        self.oids
+            .chunks(self.format.oid_len())
+            .zip(self.offsets.chunks(4))
+            .map(move |(oid, offset)| {
+                let offset = be_u32(offset);
+                if offset & LARGE_OFFSET == 0 {
+                    return (oid, u64::from(offset));
+                }
+
+                let start = (offset & !LARGE_OFFSET) as usize * 8;
+                (oid, be_u64(&large[start..]))
+            })
+    }
+
+    /// Build a trie of every object id to its offset in the pack
+    pub fn to_trie(&self) -> Result<ByteTrie<u64>, Error> {
+        ByteTrie::from_sorted_iter(self.iter()).map_err(Error::Unsorted)
+    }
+}
+
+/// Read a pack index file into a trie of every object id to its offset in the pack
+pub fn read_pack_index<P: AsRef<Path>>(
+    path: P,
+    format: ObjectFormat,
+) -> Result<ByteTrie<u64>, Error> {
+    let bytes = fs::read(path)?;
+    PackIndex::parse(&bytes, format)?.to_trie()
+}
+
+/// Insert every loose object under an objects directory, keyed by object id.
+///
+/// The value is what `value` returns for the path of the object file, so
+/// loose objects can go into the same trie as the offsets of a pack index.
+/// Directories that are not two characters long, like `pack` and `info`, are
+/// skipped, as are temporary object files.  Returns the number of objects
+/// inserted.
+pub fn read_loose_objects<P, T, V, F>(
+    objects: P,
+    format: ObjectFormat,
+    trie: &mut T,
+    mut value: F,
+) -> Result<usize, Error>
+where
+    P: AsRef<Path>,
+    T: BytesTrie<V>,
+    F: FnMut(&Path) -> V,
+{
+    let mut count = 0;
+    for fanout in fs::read_dir(objects)? {
+        let fanout = fanout?;
+        if fanout.file_name().len() != 2 {
+            continue;
+        }
+
+        let path = fanout.path();
+        if !path.is_dir() {
+            return Err(Error::Name(path));
+        }
+        let prefix = decode_name(&path)?;
+
+        for object in fs::read_dir(&path)? {
+            let path = object?.path();
+            let name = path.file_name().and_then(|name| name.to_str());
+            if name.is_some_and(|name| name.starts_with(TEMPORARY_OBJECT)) {
+                continue;
+            }
+
+            let mut oid = prefix.clone();
+            oid.extend(decode_name(&path)?);
+            if oid.len() != format.oid_len() {
+                return Err(Error::Name(path));
+            }
+
+            trie.insert(&oid, value(&path));
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Decode the file name of `path`, which git always writes in lowercase hex
+fn decode_name(path: &Path) -> Result<Vec<u8>, Error> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| !name.bytes().any(|byte| byte.is_ascii_uppercase()))
+        .ok_or_else(|| Error::Name(path.to_path_buf()))?;
+    hex::decode(name).map_err(|err| Error::Hex(path.to_path_buf(), err))
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes[..4].try_into().unwrap())
+}
+
+fn be_u64(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes[..8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tries::NibbleTrie;
+    use crate::BytesKey;
+    use std::{env, process};
+
+    /// A fresh objects directory for a test, removed again on drop
+    struct Objects(PathBuf);
+
+    impl Objects {
+        fn new(test: &str) -> Self {
+            let dir = env::temp_dir().join(format!("byte_trie-{}-{}", test, process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("pack")).unwrap();
+            fs::create_dir_all(dir.join("info")).unwrap();
+            Objects(dir)
+        }
+
+        fn add(&self, oid: &str) -> PathBuf {
+            let fanout = self.0.join(&oid[..2]);
+            fs::create_dir_all(&fanout).unwrap();
+            let path = fanout.join(&oid[2..]);
+            fs::write(&path, b"").unwrap();
+            path
+        }
+    }
+
+    impl Drop for Objects {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Entries from `git show-index`, as `offset oid (crc)` lines
+    fn show_index(listing: &str) -> Vec<(Vec<u8>, u64)> {
+        let mut entries: Vec<_> = listing
+            .lines()
+            .map(|line| {
+                let mut fields = line.split(' ');
+                let offset = fields.next().unwrap().parse().unwrap();
+                (hex::decode(fields.next().unwrap()).unwrap(), offset)
+            })
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    /// A SHA-1 pack index of sorted entries, with zeroed CRCs and checksums
+    fn pack_index(entries: &[([u8; 20], u64)]) -> Vec<u8> {
+        let mut bytes = IDX_SIGNATURE.to_vec();
+        bytes.extend_from_slice(&2u32.to_be_bytes());
+        for byte in 0..256 {
+            let count = entries
+                .iter()
+                .filter(|(oid, _)| oid[0] as usize <= byte)
+                .count();
+            bytes.extend_from_slice(&(count as u32).to_be_bytes());
+        }
+
+        entries
+            .iter()
+            .for_each(|(oid, _)| bytes.extend_from_slice(oid));
+        entries
+            .iter()
+            .for_each(|_| bytes.extend_from_slice(&[0; 4]));
+
+        let mut large = Vec::new();
+        for &(_, offset) in entries {
+            let offset = if offset < u64::from(LARGE_OFFSET) {
+                offset as u32
+            } else {
+                large.extend_from_slice(&offset.to_be_bytes());
+                LARGE_OFFSET | (large.len() / 8 - 1) as u32
+            };
+            bytes.extend_from_slice(&offset.to_be_bytes());
+        }
+
+        bytes.extend(large);
+        bytes.extend_from_slice(&[0; 40]);
+        bytes
+    }
+
+    fn entries(trie: &ByteTrie<u64>) -> Vec<(Vec<u8>, u64)> {
+        trie.iter()
+            .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+            .collect()
+    }
+
+    #[test]
+    fn matches_git_show_index() {
+        let fixtures: [(&[u8], &str, ObjectFormat); 2] = [
+            (
+                include_bytes!("../fixtures/git/sha1.idx"),
+                include_str!("../fixtures/git/sha1.idx.txt"),
+                ObjectFormat::Sha1,
+            ),
+            (
+                include_bytes!("../fixtures/git/sha256.idx"),
+                include_str!("../fixtures/git/sha256.idx.txt"),
+                ObjectFormat::Sha256,
+            ),
+        ];
+
+        for &(bytes, listing, format) in fixtures.iter() {
+            let index = PackIndex::parse(bytes, format).unwrap();
+            assert_eq!(index.format(), format);
+            assert_eq!(index.len(), 36);
+            assert_eq!(entries(&index.to_trie().unwrap()), show_index(listing));
+        }
+
+        let sha256 = fixtures[1].0;
+        let wrong_format = PackIndex::parse(sha256, ObjectFormat::Sha1);
+        assert!(matches!(wrong_format, Err(Error::Length(_))));
+    }
+
+    #[test]
+    fn reads_large_offsets() {
+        let bytes = pack_index(&[
+            ([0x01; 20], 12),
+            ([0x80; 20], 1 << 40),
+            ([0xff; 20], u64::from(LARGE_OFFSET)),
+        ]);
+
+        let trie = PackIndex::parse(&bytes, ObjectFormat::Sha1)
+            .unwrap()
+            .to_trie()
+            .unwrap();
+        assert_eq!(trie.get(&[0x01; 20]), Some(&12));
+        assert_eq!(trie.get(&[0x80; 20]), Some(&(1 << 40)));
+        assert_eq!(trie.get(&[0xff; 20]), Some(&u64::from(LARGE_OFFSET)));
+    }
+
+    #[test]
+    fn rejects_malformed_indices() {
+        let parse = |bytes| PackIndex::parse(bytes, ObjectFormat::Sha1);
+        let bytes = pack_index(&[([0x01; 20], 12), ([0x80; 20], 1 << 40)]);
+
+        let truncated = parse(&bytes[..bytes.len() - 1]);
+        assert!(matches!(truncated, Err(Error::Length(_))));
+
+        let mut version = bytes.clone();
+        version[7] = 3;
+        assert!(matches!(parse(&version), Err(Error::Version(3))));
+
+        // the count for first bytes up to 0x7f drops below the one for 0x01
+        let mut fanout = bytes.clone();
+        fanout[8 + 4 * 0x7f + 3] = 0;
+        assert!(matches!(parse(&fanout), Err(Error::Fanout)));
+
+        // a count this large overflows the table sizes on 32 bit targets
+        let mut count = bytes.clone();
+        count[IDX_HEADER - 4..IDX_HEADER].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(parse(&count), Err(Error::Length(_))));
+
+        let duplicate = pack_index(&[([0x01; 20], 12), ([0x80; 20], 24), ([0x80; 20], 36)]);
+        assert!(matches!(parse(&duplicate), Err(Error::Duplicate(2))));
+
+        // point the large offset past the end of the large offset table
+        let mut offset = bytes.clone();
+        let last_offset = bytes.len() - 40 - 8 - 1;
+        offset[last_offset] = 1;
+        assert!(matches!(parse(&offset), Err(Error::Offset(1))));
+
+        let unsorted = pack_index(&[([0x80; 20], 12), ([0x01; 20], 24)]);
+        let unsorted = parse(&unsorted).unwrap().to_trie();
+        assert!(matches!(unsorted, Err(Error::Unsorted(_))));
+    }
+
+    #[test]
+    fn reads_loose_objects() {
+        let objects = Objects::new("loose");
+        let listing = include_str!("../fixtures/git/sha256.idx.txt");
+        let mut expected: Vec<_> = listing
+            .lines()
+            .map(|line| {
+                let oid = line.split(' ').nth(1).unwrap();
+                (hex::decode(oid).unwrap(), objects.add(oid))
+            })
+            .collect();
+        expected.sort();
+        objects.add("01tmp_obj_a1b2c3");
+
+        let mut trie = NibbleTrie::new();
+        let count = read_loose_objects(
+            &objects.0,
+            ObjectFormat::Sha256,
+            &mut trie,
+            Path::to_path_buf,
+        );
+        assert_eq!(count.unwrap(), expected.len());
+
+        let entries: Vec<_> = trie
+            .iter()
+            .map(|(k, v)| (k.to_bytes().unwrap(), v.clone()))
+            .collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn reads_loose_objects_alongside_packs() {
+        const LOOSE: u64 = u64::MAX;
+        let objects = Objects::new("alongside");
+        let loose = [
+            "00112233445566778899aabbccddeeff00112233",
+            "ffffffffffffffffffffffffffffffffffffffff",
+        ];
+        for oid in loose.iter() {
+            objects.add(oid);
+        }
+
+        let pack = include_bytes!("../fixtures/git/sha1.idx");
+        let mut trie = PackIndex::parse(pack, ObjectFormat::Sha1)
+            .and_then(|index| index.to_trie())
+            .unwrap();
+        let count = read_loose_objects(&objects.0, ObjectFormat::Sha1, &mut trie, |_| LOOSE);
+        assert_eq!(count.unwrap(), 2);
+
+        let mut expected = show_index(include_str!("../fixtures/git/sha1.idx.txt"));
+        expected.extend(loose.iter().map(|oid| (hex::decode(oid).unwrap(), LOOSE)));
+        expected.sort();
+        assert_eq!(entries(&trie), expected);
+    }
+
+    #[test]
+    fn rejects_malformed_loose_objects() {
+        let objects = Objects::new("malformed");
+        objects.add("0123456789abcdef0123456789abcdef01234567");
+
+        let mut trie = ByteTrie::new();
+        let read = read_loose_objects(&objects.0, ObjectFormat::Sha256, &mut trie, |_| ());
+        assert!(matches!(read, Err(Error::Name(_))));
+
+        let read = read_loose_objects(&objects.0, ObjectFormat::Sha1, &mut trie, |_| ());
+        assert_eq!(read.unwrap(), 1);
+
+        let path = objects.add("0123456789ABCDEF0123456789abcdef01234567");
+        let read = read_loose_objects(&objects.0, ObjectFormat::Sha1, &mut trie, |_| ());
+        assert!(matches!(read, Err(Error::Name(name)) if name == path));
+
+        fs::remove_file(path).unwrap();
+        fs::create_dir(objects.0.join("zz")).unwrap();
+        let read = read_loose_objects(&objects.0, ObjectFormat::Sha1, &mut trie, |_| ());
+        assert!(matches!(
+            read,
+            Err(Error::Hex(_, HexError::InvalidCharacter { index: 0, .. }))
+        ));
+    }
+}
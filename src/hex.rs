@@ -0,0 +1,176 @@
+//! Hex string keys.
+//!
+//! Hex keys are decoded into bytes and then into key representation, the same
+//! as keys given as bytes.  A hex key needs an even number of digits, unless
+//! a digit is a whole number of symbols, like a single nibble or four bits.
+
+use crate::BytesKey;
+use std::error::Error;
+use std::fmt;
+
+/// A hex key couldn't be decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HexError {
+    /// The character at this byte position is not a hex digit
+    InvalidCharacter {
+        /// Byte position of the character in the key
+        index: usize,
+        /// The character that isn't a hex digit
+        character: char,
+    },
+    /// An odd number of digits, which doesn't end on a symbol of the key
+    OddLength(usize),
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HexError::InvalidCharacter { index, character } => write!(
+                f,
+                "invalid hex character {:?} at index {}",
+                character, index
+            ),
+            HexError::OddLength(len) => {
+                write!(f, "hex key of {} digits doesn't fit whole bytes", len)
+            }
+        }
+    }
+}
+
+impl Error for HexError {}
+
+/// Decode a hex key the way `from_bytes` reads bytes.
+///
+/// An odd number of digits is only a key when the symbol width divides 4, so
+/// the last digit ends on a symbol.
+pub(crate) fn decode_key<K: BytesKey>(hex: &str) -> Result<K, HexError> {
+    let width = K::symbols(&[]).width();
+    match decode(hex) {
+        Ok(bytes) => Ok(K::from_bytes(&bytes)),
+        Err(HexError::OddLength(_)) if 4 % width == 0 => {
+            let mut key = K::from_bytes(&bytes(&digits(hex)?));
+            // drop the symbols of the zero digit padding the last byte
+            key.split_off(key.symbol_len() - 4 / width);
+            Ok(key)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Decode hex digits in either case into whole bytes
+pub(crate) fn decode(hex: &str) -> Result<Vec<u8>, HexError> {
+    let digits = digits(hex)?;
+    if digits.len() % 2 != 0 {
+        return Err(HexError::OddLength(digits.len()));
+    }
+
+    Ok(bytes(&digits))
+}
+
+/// Pack digits into bytes, padding an odd last digit with a zero digit
+fn bytes(digits: &[u8]) -> Vec<u8> {
+    digits
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair.get(1).unwrap_or(&0))
+        .collect()
+}
+
+/// The value of every hex digit, in either case
+pub(crate) fn digits(hex: &str) -> Result<Vec<u8>, HexError> {
+    hex.char_indices()
+        .map(|(index, character)| match character.to_digit(16) {
+            Some(digit) => Ok(digit as u8),
+            None => Err(HexError::InvalidCharacter { index, character }),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::RadixKey;
+    use crate::prelude::*;
+    use crate::tries::Trie;
+
+    #[test]
+    fn matches_byte_keys() {
+        let mut bytes = BitTrie::new();
+        let mut hex = BitTrie::new();
+        for (i, key) in [&[0xab, 0x01][..], &[0xab], &[], &[0x0f, 0xff]]
+            .iter()
+            .enumerate()
+        {
+            bytes.insert(key, i);
+            let digits: String = key.iter().map(|byte| format!("{:02X}", byte)).collect();
+            hex.insert_hex(&digits, i).unwrap();
+        }
+
+        let entries = |trie: &BitTrie<usize>| -> Vec<_> {
+            trie.iter()
+                .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+                .collect()
+        };
+        assert_eq!(entries(&hex), entries(&bytes));
+        assert_eq!(hex.get_hex("ab01"), Ok(Some(&0)));
+        assert_eq!(hex.remove_hex("0fff"), Ok(Some(3)));
+        assert_eq!(hex.get(&[0x0f, 0xff]), None);
+    }
+
+    #[test]
+    fn odd_lengths_end_on_whole_symbols() {
+        let mut trie = NibbleTrie::new();
+        trie.insert_hex("abc", 0).unwrap();
+        trie.insert_hex("ab", 1).unwrap();
+        trie.insert(&[0xab, 0xcd], 2);
+
+        assert_eq!(trie.get_hex("abc"), Ok(Some(&0)));
+        assert_eq!(trie.get_hex("abcd"), Ok(Some(&2)));
+        assert_eq!(trie.get(&[0xab]), Some(&1));
+
+        let keys: Vec<_> = trie
+            .iter()
+            .map(|(k, _)| k.iter().collect::<Vec<_>>())
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                vec![0xa, 0xb],
+                vec![0xa, 0xb, 0xc],
+                vec![0xa, 0xb, 0xc, 0xd]
+            ]
+        );
+
+        let mut bits = BitTrie::new();
+        bits.insert_hex("a", 0).unwrap();
+        let key = bits.iter().next().unwrap().0;
+        assert_eq!(key.iter().collect::<Vec<_>>(), vec![1, 0, 1, 0]);
+        assert_eq!(bits.remove_hex("a"), Ok(Some(0)));
+
+        let mut threes = Trie::<RadixKey<3>, ()>::new();
+        assert_eq!(threes.insert_hex("abc", ()), Err(HexError::OddLength(3)));
+        threes.insert_hex("abcd", ()).unwrap();
+        assert_eq!(threes.get(&[0xab, 0xcd]), Some(&()));
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        let mut trie = ByteTrie::new();
+        assert_eq!(trie.insert_hex("abc", 0), Err(HexError::OddLength(3)));
+        assert_eq!(
+            trie.get_hex("a-"),
+            Err(HexError::InvalidCharacter {
+                index: 1,
+                character: '-'
+            })
+        );
+
+        let mut trie = NibbleTrie::<()>::new();
+        assert_eq!(
+            trie.remove_hex("éa"),
+            Err(HexError::InvalidCharacter {
+                index: 0,
+                character: 'é'
+            })
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Byte storage that keeps short key fragments inline.
+//!
+//! Most key fragments are a few bytes near the root and under a full oid at
+//! the leaves, so giving each one its own heap allocation costs more than the
+//! fragment itself.  Fragments that fit in the space of a `Vec` are stored in
+//! place instead, and only longer ones go to the heap.
+
+use std::fmt;
+
+/// The most bytes stored inline, keeping the storage the same size as a `Vec`
+pub(crate) const INLINE_LEN: usize = 22;
+
+/// A byte vector that stores up to `INLINE_LEN` bytes without allocating.
+///
+/// Heap bytes are a boxed slice rather than a `Vec`, which leaves room for the
+/// inline bytes next to the enum tag.  Growing them reallocates every time,
+/// but keys are almost always built whole and only split afterwards.
+#[derive(Clone)]
+pub(crate) enum InlineBytes {
+    Inline { len: u8, bytes: [u8; INLINE_LEN] },
+    Heap(Box<[u8]>),
+}
+
+impl InlineBytes {
+    pub(crate) fn from_slice(slice: &[u8]) -> Self {
+        if slice.len() > INLINE_LEN {
+            return InlineBytes::Heap(slice.into());
+        }
+
+        let mut bytes = [0; INLINE_LEN];
+        bytes[..slice.len()].copy_from_slice(slice);
+        InlineBytes::Inline {
+            len: slice.len() as u8,
+            bytes,
+        }
+    }
+
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        match self {
+            InlineBytes::Inline { len, bytes } => &bytes[..*len as usize],
+            InlineBytes::Heap(heap) => heap,
+        }
+    }
+
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+        match self {
+            InlineBytes::Inline { len, bytes } => &mut bytes[..*len as usize],
+            InlineBytes::Heap(heap) => heap,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    /// Append bytes, moving to the heap once they don't fit inline
+    pub(crate) fn extend_from_slice(&mut self, other: &[u8]) {
+        match self {
+            InlineBytes::Inline { len, bytes } if *len as usize + other.len() <= INLINE_LEN => {
+                let start = *len as usize;
+                bytes[start..start + other.len()].copy_from_slice(other);
+                *len += other.len() as u8;
+            }
+            _ => {
+                let mut vec = Vec::with_capacity(self.len() + other.len());
+                vec.extend_from_slice(self.as_slice());
+                vec.extend_from_slice(other);
+                *self = InlineBytes::Heap(vec.into_boxed_slice());
+            }
+        }
+    }
+
+    /// Shorten to `len` bytes, moving back inline if they fit
+    pub(crate) fn truncate(&mut self, len: usize) {
+        match self {
+            InlineBytes::Inline {
+                len: current,
+                bytes,
+            } => {
+                if len < *current as usize {
+                    bytes[len..].iter_mut().for_each(|byte| *byte = 0);
+                    *current = len as u8;
+                }
+            }
+            InlineBytes::Heap(heap) if len < heap.len() => *self = Self::from_slice(&heap[..len]),
+            InlineBytes::Heap(_) => {}
+        }
+    }
+
+    pub(crate) fn split_off(&mut self, at: usize) -> Self {
+        let rest = Self::from_slice(&self.as_slice()[at..]);
+        self.truncate(at);
+        rest
+    }
+}
+
+impl From<Vec<u8>> for InlineBytes {
+    fn from(vec: Vec<u8>) -> Self {
+        if vec.len() > INLINE_LEN {
+            InlineBytes::Heap(vec.into_boxed_slice())
+        } else {
+            Self::from_slice(&vec)
+        }
+    }
+}
+
+impl Default for InlineBytes {
+    fn default() -> Self {
+        Self::from_slice(&[])
+    }
+}
+
+impl fmt::Debug for InlineBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_slice(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_between_inline_and_heap() {
+        let all: Vec<u8> = (0..40).collect();
+        let mut bytes = InlineBytes::default();
+        for &byte in &all {
+            bytes.extend_from_slice(&[byte]);
+            assert_eq!(bytes.as_slice(), &all[..bytes.len()]);
+        }
+        assert!(matches!(bytes, InlineBytes::Heap(_)));
+
+        let rest = bytes.split_off(INLINE_LEN);
+        assert!(matches!(rest, InlineBytes::Inline { .. }));
+        assert!(matches!(bytes, InlineBytes::Inline { .. }));
+        assert_eq!(rest.as_slice(), &all[INLINE_LEN..]);
+
+        bytes.truncate(3);
+        bytes.extend_from_slice(&[0xff; 2]);
+        assert_eq!(bytes.as_slice(), &[0, 1, 2, 0xff, 0xff]);
+        assert_eq!(
+            std::mem::size_of::<InlineBytes>(),
+            std::mem::size_of::<Vec<u8>>()
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Ordered iteration over `AdaptiveNode`s.
+//!
+//! Child buckets place nodes by `byte % size`, so slot order is not key order.
+//! We flatten each bucket (including the empty keyed nodes that hold collided
+//! slots) and sort it by first key byte before visiting it.  Nodes waiting to
+//! be visited sit in a single deque in key order, which lets both ends of the
+//! iterator expand nodes independently without ever overlapping.
+
+use crate::{AdaptiveNode, BytesKey};
+use std::collections::VecDeque;
+use std::ops::Bound;
+
+enum Item<'a, K: BytesKey, V> {
+    /// A node that still needs expanding, with its full key
+    Node(Vec<u8>, &'a AdaptiveNode<K, V>),
+    /// A value ready to be yielded with its full key
+    Entry(Vec<u8>, &'a V),
+}
+
+/// An ordered, double-ended iterator over the entries of a trie.
+///
+/// Yields the full key of each entry along with a reference to its value,
+/// ordered by key bytes.  Subtrees outside of the iterator bounds are never
+/// visited.
+pub struct Iter<'a, K: BytesKey, V> {
+    items: VecDeque<Item<'a, K, V>>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+
+impl<'a, K: BytesKey, V> Iter<'a, K, V> {
+    /// Iterate over all keys of `node` within the bounds, in key representation
+    pub(crate) fn new(
+        node: &'a AdaptiveNode<K, V>,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+    ) -> Self {
+        let mut iter = Self {
+            items: VecDeque::new(),
+            start,
+            end,
+        };
+
+        let key: Vec<u8> = node.key.iter().collect();
+        if iter.may_contain(&key) {
+            iter.items.push_back(Item::Node(key, node));
+        }
+
+        iter
+    }
+
+    /// If any key starting with `prefix` could be inside the bounds
+    fn may_contain(&self, prefix: &[u8]) -> bool {
+        let after_start = match &self.start {
+            Bound::Included(start) | Bound::Excluded(start) => {
+                let len = prefix.len().min(start.len());
+                prefix[..len] >= start[..len]
+            }
+            Bound::Unbounded => true,
+        };
+
+        // every key starting with `prefix` is at least `prefix` itself
+        let before_end = match &self.end {
+            Bound::Included(end) => prefix <= &end[..],
+            Bound::Excluded(end) => prefix < &end[..],
+            Bound::Unbounded => true,
+        };
+
+        after_start && before_end
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        let after_start = match &self.start {
+            Bound::Included(start) => key >= &start[..],
+            Bound::Excluded(start) => key > &start[..],
+            Bound::Unbounded => true,
+        };
+
+        let before_end = match &self.end {
+            Bound::Included(end) => key <= &end[..],
+            Bound::Excluded(end) => key < &end[..],
+            Bound::Unbounded => true,
+        };
+
+        after_start && before_end
+    }
+
+    /// Expand a node into its value and its children, in key order.
+    ///
+    /// `key` is the full key of `node`, including its own fragment.
+    fn expand(&self, key: Vec<u8>, node: &'a AdaptiveNode<K, V>) -> Vec<Item<'a, K, V>> {
+        let mut items = Vec::new();
+        for child in node.children() {
+            let mut child_key = Vec::with_capacity(key.len() + child.key.symbol_len());
+            child_key.extend_from_slice(&key);
+            child_key.extend(child.key.iter());
+            if self.may_contain(&child_key) {
+                items.push(Item::Node(child_key, child));
+            }
+        }
+
+        // a node's own key sorts before every key below it
+        if let Some(value) = &node.value {
+            if self.contains(&key) {
+                items.insert(0, Item::Entry(key, value));
+            }
+        }
+
+        items
+    }
+}
+
+impl<'a, K: BytesKey, V> Iterator for Iter<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.items.pop_front()? {
+                Item::Entry(key, value) => return Some((K::new(key), value)),
+                Item::Node(key, node) => {
+                    for item in self.expand(key, node).into_iter().rev() {
+                        self.items.push_front(item);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K: BytesKey, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.items.pop_back()? {
+                Item::Entry(key, value) => return Some((K::new(key), value)),
+                Item::Node(key, node) => {
+                    let items = self.expand(key, node);
+                    self.items.extend(items);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K: BytesKey, V> std::iter::FusedIterator for Iter<'a, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::test_util;
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+
+    fn colliding_keys() -> Vec<Vec<u8>> {
+        test_util::colliding_keys(&mut test_util::rng(), 300)
+    }
+
+    fn check_ranges<T: BytesTrie<usize>>(
+        trie: &T,
+        range: impl Fn(&T, (Bound<&[u8]>, Bound<&[u8]>)) -> Vec<(Vec<u8>, usize)>,
+    ) {
+        let keys = colliding_keys();
+        let model: BTreeMap<_, _> = keys.iter().cloned().zip(0..).collect();
+
+        let bounds = [
+            (Bound::Unbounded, Bound::Unbounded),
+            (Bound::Included(&keys[3][..]), Bound::Unbounded),
+            (
+                Bound::Excluded(&[0x40][..]),
+                Bound::Included(&[0xc0, 0x00][..]),
+            ),
+            (
+                Bound::Included(&[0x01, 0x80][..]),
+                Bound::Excluded(&[0xff][..]),
+            ),
+            (Bound::Unbounded, Bound::Excluded(&[][..])),
+        ];
+
+        for &bounds in bounds.iter() {
+            let expected: Vec<_> = model
+                .range::<[u8], _>(bounds)
+                .map(|(k, &v)| (k.clone(), v))
+                .collect();
+            assert_eq!(range(trie, bounds), expected);
+        }
+    }
+
+    #[test]
+    fn byte_range_matches_btree() {
+        let mut trie = ByteTrie::new();
+        colliding_keys()
+            .iter()
+            .zip(0..)
+            .for_each(|(k, v)| trie.insert(k, v));
+
+        check_ranges(&trie, |trie, bounds| {
+            trie.range(bounds)
+                .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+                .collect()
+        });
+
+        let forward: Vec<_> = trie.iter().map(|(_, &v)| v).collect();
+        let mut backward: Vec<_> = trie.iter().rev().map(|(_, &v)| v).collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+    }
+
+    #[test]
+    fn nibble_and_bit_ranges_use_byte_order() {
+        let mut nibbles = NibbleTrie::new();
+        let mut bits = BitTrie::new();
+        colliding_keys().iter().zip(0..).for_each(|(k, v)| {
+            nibbles.insert(k, v);
+            bits.insert(k, v);
+        });
+
+        let by_value = |values: Vec<usize>| -> Vec<(Vec<u8>, usize)> {
+            let keys = colliding_keys();
+            values.into_iter().map(|v| (keys[v].clone(), v)).collect()
+        };
+
+        check_ranges(&nibbles, |trie, bounds| {
+            by_value(trie.range(bounds).map(|(_, &v)| v).collect())
+        });
+        check_ranges(&bits, |trie, bounds| {
+            by_value(trie.range(bounds).rev().map(|(_, &v)| v).rev().collect())
+        });
+    }
+
+    #[test]
+    fn meets_in_the_middle() {
+        let mut trie = ByteTrie::new();
+        colliding_keys()
+            .iter()
+            .zip(0..)
+            .for_each(|(k, v)| trie.insert(k, v));
+
+        let len = trie.iter().count();
+        let mut iter = trie.iter();
+        let mut seen = 0;
+        loop {
+            let next = if seen % 2 == 0 {
+                iter.next()
+            } else {
+                iter.next_back()
+            };
+
+            if next.is_none() {
+                break;
+            }
+            seen += 1;
+        }
+        assert_eq!(seen, len);
+    }
+}
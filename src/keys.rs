@@ -1,7 +1,10 @@
 //! `u8` based key implementations.
 
+use crate::hex;
+use crate::inline::InlineBytes;
 pub use crate::BytesKey;
 use std::fmt;
+use std::iter::FusedIterator;
 
 /// How much of two `BytesKey`s share a prefix
 pub enum KeyMatch {
@@ -17,108 +20,451 @@ pub enum KeyMatch {
     None,
 }
 
-/// A `u8` based key representing bytes
-#[derive(Debug)]
-pub struct ByteKey(Vec<u8>);
+/// The symbols of a key, read from its bytes on the fly.
+///
+/// This lets lookups walk a trie with a key that was never converted into an
+/// owned `BytesKey`.
+#[derive(Debug, Clone)]
+pub struct KeySymbols<'a> {
+    bytes: &'a [u8],
+    /// Bits in each symbol, from 1 to 8
+    width: usize,
+    /// Position of the next symbol from the front
+    front: usize,
+    /// Position after the next symbol from the back
+    back: usize,
+}
 
-impl BytesKey for ByteKey {
-    fn new(vec: Vec<u8>) -> Self {
-        ByteKey(vec)
+impl<'a> KeySymbols<'a> {
+    /// Read `bytes` as symbols `width` bits wide, most significant bits first.
+    ///
+    /// A width of 8 reads a key that is already in key representation.  When
+    /// the width doesn't divide 8 the last symbol is padded with zero bits.
+    pub(crate) fn new(bytes: &'a [u8], width: usize) -> Self {
+        Self::packed(bytes, width, (bytes.len() * 8).div_ceil(width))
     }
 
-    fn from_bytes(bytes: &[u8]) -> Self {
-        ByteKey(bytes.to_vec())
+    /// Read the first `len` symbols packed into `bytes`
+    fn packed(bytes: &'a [u8], width: usize, len: usize) -> Self {
+        debug_assert!((1..=8).contains(&width));
+        Self {
+            bytes,
+            width,
+            front: 0,
+            back: len,
+        }
+    }
+
+    /// Bits in each symbol
+    pub(crate) fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Drop a last symbol that is padded past the end of the bytes, keeping
+    /// only the symbols the bytes fully cover
+    pub(crate) fn covered(mut self) -> Self {
+        self.back = self.back.min(self.bytes.len() * 8 / self.width);
+        self.front = self.front.min(self.back);
+        self
+    }
+
+    /// The symbol at `index`, counting from the next symbol
+    pub fn get(&self, index: usize) -> Option<u8> {
+        if index < self.len() {
+            Some(self.symbol(self.front + index))
+        } else {
+            None
+        }
+    }
+
+    /// If the remaining symbols start with the symbols of `prefix`
+    pub fn starts_with(&self, prefix: KeySymbols<'_>) -> bool {
+        prefix.len() <= self.len() && prefix.zip(self.clone()).all(|(lhs, rhs)| lhs == rhs)
     }
 
-    fn get(&self) -> &[u8] {
-        &self.0
+    /// If the remaining symbols are a prefix of the symbols of `key`
+    pub fn is_prefix_of(&self, key: KeySymbols<'_>) -> bool {
+        key.starts_with(self.clone())
     }
 
-    fn get_mut(&mut self) -> &mut Vec<u8> {
-        &mut self.0
+    /// Skip past `prefix` if the remaining symbols start with it
+    pub fn strip_prefix(&mut self, prefix: KeySymbols<'_>) -> bool {
+        let len = prefix.len();
+        let stripped = self.starts_with(prefix);
+        if stripped {
+            self.front += len;
+        }
+        stripped
+    }
+
+    fn symbol(&self, position: usize) -> u8 {
+        let bit = position * self.width;
+        let mask = 0xff >> (8 - self.width);
+        let offset = bit % 8;
+        let byte = self.bytes[bit / 8];
+        if offset + self.width <= 8 {
+            return (byte >> (8 - self.width - offset)) & mask;
+        }
+
+        // the symbol continues into the next byte, which is zero past the end
+        let next = self.bytes.get(bit / 8 + 1).copied().unwrap_or(0);
+        let word = u16::from(byte) << 8 | u16::from(next);
+        (word >> (16 - self.width - offset)) as u8 & mask
     }
 }
 
-impl fmt::Display for ByteKey {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+impl Iterator for KeySymbols<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let symbol = self.get(0)?;
+        self.front += 1;
+        Some(symbol)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<u8> {
+        self.front += n.min(self.len());
+        self.next()
     }
 }
 
-/// A `u8` based key representing nibbles
-#[derive(Debug)]
-pub struct NibbleKey(Vec<u8>);
+impl DoubleEndedIterator for KeySymbols<'_> {
+    fn next_back(&mut self) -> Option<u8> {
+        if self.front == self.back {
+            return None;
+        }
 
-impl BytesKey for NibbleKey {
-    fn new(nibble_vec: Vec<u8>) -> Self {
-        NibbleKey(nibble_vec)
+        self.back -= 1;
+        Some(self.symbol(self.back))
+    }
+}
+
+impl ExactSizeIterator for KeySymbols<'_> {}
+
+impl FusedIterator for KeySymbols<'_> {}
+
+/// Symbols packed `WIDTH` bits each into bytes, most significant bits first.
+///
+/// Symbols are a stream of bits, so with widths that don't divide 8 they can
+/// straddle two bytes.  Unused bits of the last byte are always zero.  Short
+/// keys are stored inline, without a heap allocation.
+#[derive(Debug, Clone, Default)]
+struct Packed<const WIDTH: usize> {
+    bytes: InlineBytes,
+    /// The number of symbols
+    len: usize,
+}
+
+impl<const WIDTH: usize> Packed<WIDTH> {
+    const WIDTH_IN_RANGE: () = assert!(WIDTH >= 1 && WIDTH <= 8, "symbols are 1 to 8 bits");
+
+    fn from_symbols<I: IntoIterator<Item = u8>>(symbols: I) -> Self {
+        let () = Self::WIDTH_IN_RANGE;
+        let mut bytes = Vec::new();
+        let mut len = 0;
+        for symbol in symbols {
+            // extra bits would spill into the next symbol
+            assert!(
+                u16::from(symbol) >> WIDTH == 0,
+                "symbol {:#x} is wider than {} bits",
+                symbol,
+                WIDTH
+            );
+
+            let bit = len * WIDTH;
+            if bit % 8 == 0 {
+                bytes.push(0);
+            }
+
+            let word = u16::from(symbol) << (16 - WIDTH - bit % 8);
+            let last = bytes
+                .last_mut()
+                .expect("the symbol starts in the last byte");
+            *last |= (word >> 8) as u8;
+            if bit % 8 + WIDTH > 8 {
+                bytes.push(word as u8);
+            }
+            len += 1;
+        }
+
+        Self {
+            bytes: bytes.into(),
+            len,
+        }
     }
 
     fn from_bytes(bytes: &[u8]) -> Self {
-        NibbleKey(
-            bytes
-                .iter()
-                .fold(Vec::with_capacity(bytes.len() * 2), |mut vec, byte| {
-                    vec.push(byte >> 4);
-                    vec.push(byte & 0x0F);
-                    vec
-                }),
-        )
+        let () = Self::WIDTH_IN_RANGE;
+        let len = (bytes.len() * 8).div_ceil(WIDTH);
+        let mut packed = InlineBytes::from_slice(bytes);
+
+        // the padding of the last symbol can spill into a byte of its own
+        if (len * WIDTH).div_ceil(8) > bytes.len() {
+            packed.extend_from_slice(&[0]);
+        }
+        Self { bytes: packed, len }
     }
 
-    fn get(&self) -> &[u8] {
-        &self.0
+    fn to_bytes(&self) -> Option<Vec<u8>> {
+        // keys of whole bytes have exactly enough symbols to cover the bytes,
+        // with the last one padded by zero bits
+        let bytes = self.bytes.as_slice();
+        let whole = self.len * WIDTH / 8;
+        let padding = &bytes[whole..];
+        if (whole * 8).div_ceil(WIDTH) == self.len && padding.iter().all(|&byte| byte == 0) {
+            Some(bytes[..whole].to_vec())
+        } else {
+            None
+        }
     }
 
-    fn get_mut(&mut self) -> &mut Vec<u8> {
-        &mut self.0
+    fn iter(&self) -> KeySymbols<'_> {
+        KeySymbols::packed(self.bytes.as_slice(), WIDTH, self.len)
     }
-}
 
-impl fmt::Display for NibbleKey {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.0.iter().try_for_each(|b| write!(f, "{:x}", b))
+    fn split_off(&mut self, at: usize) -> Self {
+        assert!(at <= self.len, "split index out of bounds");
+
+        // splitting between bytes doesn't need any shifting
+        let bit = at * WIDTH;
+        let rest = if bit % 8 == 0 {
+            Self {
+                bytes: self.bytes.split_off(bit / 8),
+                len: self.len - at,
+            }
+        } else {
+            Self::from_symbols(self.iter().skip(at))
+        };
+
+        self.bytes.truncate(bit.div_ceil(8));
+        let used = bit % 8;
+        if let (Some(last), true) = (self.bytes.as_mut_slice().last_mut(), used != 0) {
+            *last &= !(0xff >> used);
+        }
+
+        self.len = at;
+        rest
+    }
+
+    fn append(&mut self, other: &Self) {
+        if self.len * WIDTH % 8 == 0 {
+            self.bytes.extend_from_slice(other.bytes.as_slice());
+            self.len += other.len;
+        } else {
+            *self = Self::from_symbols(self.iter().chain(other.iter()));
+        }
+    }
+
+    fn common_prefix(&self, other: &Self) -> usize {
+        let bytes = self
+            .bytes
+            .as_slice()
+            .iter()
+            .zip(other.bytes.as_slice())
+            .take_while(|(lhs, rhs)| lhs == rhs)
+            .count();
+
+        // the first differing byte can still start with equal symbols
+        let aligned = bytes * 8 / WIDTH;
+        let rest = self
+            .iter()
+            .skip(aligned)
+            .zip(other.iter().skip(aligned))
+            .take_while(|(lhs, rhs)| lhs == rhs)
+            .count();
+
+        (aligned + rest).min(self.len).min(other.len)
     }
 }
 
-/// A `u8` based key representing bits
-#[derive(Debug)]
-pub struct BitKey(Vec<u8>);
+/// A key of symbols `BITS` wide, packed into bytes most significant bits first.
+///
+/// Any width from 1 to 8 bits works, like 2 bit DNA bases, 5 bit base32 or
+/// 6 bit base64 digits.  Bytes are read as a stream of bits, so a symbol can
+/// straddle two bytes and the last symbol read from bytes may be padded with
+/// zero bits.  Symbols display as hex digits, two per symbol above 4 bits.
+#[derive(Debug, Clone)]
+pub struct RadixKey<const BITS: usize>(Packed<BITS>);
 
-impl BytesKey for BitKey {
-    fn new(bit_vec: Vec<u8>) -> Self {
-        BitKey(bit_vec)
+impl<const BITS: usize> BytesKey for RadixKey<BITS> {
+    fn new(symbols: Vec<u8>) -> Self {
+        RadixKey(Packed::from_symbols(symbols))
     }
 
     fn from_bytes(bytes: &[u8]) -> Self {
-        BitKey(
-            bytes
-                .iter()
-                .fold(Vec::with_capacity(bytes.len() * 8), |mut vec, byte| {
-                    vec.push(byte & 0x1);
-                    vec.push(byte & 0x2);
-                    vec.push(byte & 0x4);
-                    vec.push(byte & 0x8);
-                    vec.push(byte & 0x10);
-                    vec.push(byte & 0x20);
-                    vec.push(byte & 0x40);
-                    vec.push(byte & 0x80);
-                    vec
-                }),
-        )
-    }
-
-    fn get(&self) -> &[u8] {
-        &self.0
-    }
-
-    fn get_mut(&mut self) -> &mut Vec<u8> {
-        &mut self.0
+        RadixKey(Packed::from_bytes(bytes))
+    }
+
+    fn symbols(bytes: &[u8]) -> KeySymbols<'_> {
+        KeySymbols::new(bytes, BITS)
+    }
+
+    fn to_bytes(&self) -> Option<Vec<u8>> {
+        self.0.to_bytes()
+    }
+
+    fn from_display(fragment: &str) -> Option<Self> {
+        let digits = hex::digits(fragment).ok()?;
+        let symbols = if BITS <= 4 {
+            digits
+        } else if digits.len() % 2 == 0 {
+            digits
+                .chunks(2)
+                .map(|pair| pair[0] << 4 | pair[1])
+                .collect()
+        } else {
+            return None;
+        };
+
+        // `new` would reject the symbols that don't fit
+        let max = (1u16 << BITS) - 1;
+        symbols
+            .iter()
+            .all(|&symbol| u16::from(symbol) <= max)
+            .then(|| Self::new(symbols))
+    }
+
+    fn iter(&self) -> KeySymbols<'_> {
+        self.0.iter()
+    }
+
+    fn symbol_len(&self) -> usize {
+        self.0.len
+    }
+
+    fn split_off(&mut self, at: usize) -> Self {
+        RadixKey(self.0.split_off(at))
+    }
+
+    fn append(&mut self, other: &Self) {
+        self.0.append(&other.0)
+    }
+
+    fn common_prefix(&self, other: &Self) -> usize {
+        self.0.common_prefix(&other.0)
     }
 }
 
-impl fmt::Display for BitKey {
+impl<const BITS: usize> fmt::Display for RadixKey<BITS> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.0.iter().try_for_each(|b| write!(f, "{:b}", b))
+        self.iter().try_for_each(|symbol| {
+            if BITS <= 4 {
+                write!(f, "{:x}", symbol)
+            } else {
+                write!(f, "{:02x}", symbol)
+            }
+        })
+    }
+}
+
+/// A `u8` based key representing bytes
+pub type ByteKey = RadixKey<8>;
+
+/// A `u8` based key representing nibbles, packed two to a byte
+pub type NibbleKey = RadixKey<4>;
+
+/// A `u8` based key representing bits, packed eight to a byte with the most
+/// significant bit first
+pub type BitKey = RadixKey<1>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbols<K: BytesKey>(key: &K) -> Vec<u8> {
+        key.iter().collect()
+    }
+
+    fn round_trip<K: BytesKey>(bytes: &[u8], symbols_len: usize) {
+        let key = K::from_bytes(bytes);
+        assert_eq!(key.symbol_len(), symbols_len);
+        assert_eq!(key.to_bytes().as_deref(), Some(bytes));
+        assert!(K::symbols(bytes).eq(key.iter()));
+        assert!(K::symbols(bytes).rev().eq(key.iter().rev()));
+
+        // byte keys can't end in the middle of a byte
+        if symbols_len > bytes.len() {
+            let partial = K::new(symbols(&key)[..symbols_len - 1].to_vec());
+            assert_eq!(partial.to_bytes(), None);
+        }
+    }
+
+    #[test]
+    fn to_bytes_reverses_from_bytes() {
+        let bytes = [0x00, 0x7f, 0x80, 0xa5, 0xff];
+        round_trip::<ByteKey>(&bytes, 5);
+        round_trip::<NibbleKey>(&bytes, 10);
+        round_trip::<BitKey>(&bytes, 40);
+        round_trip::<RadixKey<2>>(&bytes, 20);
+        round_trip::<RadixKey<5>>(&bytes, 8);
+        round_trip::<RadixKey<6>>(&bytes, 7);
+        round_trip::<RadixKey<7>>(&bytes[..1], 2);
+    }
+
+    #[test]
+    fn radix_keys_straddle_bytes() {
+        let bytes = [0b1010_1100, 0b0111_0001, 0b1111_0000];
+        let key = RadixKey::<5>::from_bytes(&bytes);
+        assert_eq!(symbols(&key), [0b10101, 0b10001, 0b11000, 0b11111, 0b00000]);
+        assert_eq!(key.to_string(), "1511181f00");
+
+        for at in 0..=key.symbol_len() {
+            let mut head = key.clone();
+            let rest = head.split_off(at);
+            assert_eq!(symbols(&head), symbols(&key)[..at]);
+            assert_eq!(head.common_prefix(&key), at);
+
+            head.append(&rest);
+            assert_eq!(head.to_bytes().as_deref(), Some(&bytes[..]));
+        }
+
+        // a byte more is always a symbol more, so byte keys stay distinct
+        let shorter = RadixKey::<6>::from_bytes(&bytes[..2]);
+        assert_eq!(shorter.symbol_len(), 3);
+        assert_eq!(shorter.to_bytes().as_deref(), Some(&bytes[..2]));
+        assert_eq!(
+            RadixKey::<6>::new(vec![0b101011, 0b000111]).to_bytes(),
+            None
+        );
+    }
+
+    #[test]
+    fn packed_keys_split_and_append() {
+        let bytes = [0x12, 0x34, 0x56];
+        for at in 0..=24 {
+            let mut key = BitKey::from_bytes(&bytes);
+            let all = symbols(&key);
+            let rest = key.split_off(at);
+            assert_eq!(symbols(&key), all[..at]);
+            assert_eq!(symbols(&rest), all[at..]);
+            assert_eq!(key.common_prefix(&BitKey::from_bytes(&bytes)), at);
+
+            key.append(&rest);
+            assert_eq!(key.to_bytes().as_deref(), Some(&bytes[..]));
+        }
+
+        let mut key = NibbleKey::new(vec![0x1, 0x2, 0x3]);
+        assert_eq!(key.to_string(), "123");
+        key.append(&NibbleKey::new(vec![0x4]));
+        assert_eq!(key.to_bytes(), Some(vec![0x12, 0x34]));
+        assert_eq!(key.common_prefix(&NibbleKey::new(vec![0x1, 0x2, 0x4])), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "symbol 0x1f is wider than 4 bits")]
+    fn rejects_symbols_wider_than_the_key() {
+        NibbleKey::new(vec![0x0, 0x1f]);
+    }
+
+    #[test]
+    fn symbols_fill_their_width() {
+        assert_eq!(BitKey::new(vec![1]).to_string(), "1");
+        assert_eq!(RadixKey::<5>::new(vec![0x1f]).iter().next(), Some(0x1f));
+        assert!(std::panic::catch_unwind(|| BitKey::new(vec![2])).is_err());
     }
 }
@@ -1,15 +1,40 @@
 #![doc(html_root_url = "https://docs.rs/byte_trie/0.3.0")]
 
 use crate::child::Child;
-use crate::keys::KeyMatch;
+use crate::entry::Entry;
+use crate::keys::{KeyMatch, KeySymbols};
 use std::fmt::Display;
 
 mod child;
+#[cfg(feature = "concurrent")]
+pub mod concurrent;
+pub mod cursor;
+pub mod diff;
+pub mod dot;
+pub mod entry;
+pub mod fixed;
+#[cfg(feature = "git")]
+pub mod git;
+pub mod hex;
+mod inline;
+pub mod iter;
 pub mod keys;
+mod merge;
 pub mod nodes;
+pub mod persistent;
+mod pretty;
+#[cfg(feature = "rayon")]
+mod rayon;
 #[cfg(feature = "serde")]
 mod serde;
+#[cfg(feature = "serde")]
+pub use crate::serde::SerdeKey;
+pub mod sets;
+pub mod sorted;
+#[cfg(test)]
+mod test_util;
 pub mod tries;
+pub mod typed;
 
 /// Represents a trie with node keys having the maximum size of `u8`.
 ///
@@ -23,6 +48,59 @@ pub trait BytesTrie<T> {
     fn insert(&mut self, key: &[u8], value: T);
 }
 
+/// The map interface of a trie, for code generic over its key representation.
+///
+/// Only lookups, removal and iteration need implementing, everything else has
+/// a default built on them.  Iteration can use any iterator of key and value.
+pub trait BytesTrieExt<T>: BytesTrie<T> {
+    /// The key type keys are stored as
+    type Key: BytesKey;
+
+    /// The iterator of `iter` and `iter_prefix`, in key order
+    type Iter<'a>: Iterator<Item = (Self::Key, &'a T)>
+    where
+        Self: 'a,
+        T: 'a;
+
+    /// Get a reference to the value of a key
+    fn get(&self, key: &[u8]) -> Option<&T>;
+
+    /// Get a mutable reference to the value of a key
+    fn get_mut(&mut self, key: &[u8]) -> Option<&mut T>;
+
+    /// Remove a key from the trie, returning its value if it existed
+    fn remove(&mut self, key: &[u8]) -> Option<T>;
+
+    /// Iterate over all entries in key order
+    fn iter(&self) -> Self::Iter<'_>;
+
+    /// Iterate over the entries with keys starting with `prefix`, in key order
+    fn iter_prefix(&self, prefix: &[u8]) -> Self::Iter<'_>;
+
+    /// If the trie contains a value for the key
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// The number of keys in the trie
+    fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// If the trie has no keys
+    fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// The entry of a key, for inserting or updating its value in place
+    fn entry(&mut self, key: &[u8]) -> Entry<'_, Self, T>
+    where
+        Self: Sized,
+    {
+        Entry::new(self, key)
+    }
+}
+
 /// A byte-driven representation of an adaptive compressed trie node.
 ///
 /// Largest key's value can be a `u8`, here supplied by `BytesKey`.
@@ -33,33 +111,69 @@ pub struct AdaptiveNode<K: BytesKey, V> {
     pub(crate) child: Option<Child<K, V>>,
 }
 
-/// A way of representing a `BytesNode` key `u8` inside a `Vec<u8>`.
+/// A way of representing a `BytesNode` key as `u8` symbols.
 ///
-/// Keeps track of `u8` representation and serialization presentation.
+/// Keeps track of `u8` representation and serialization presentation.  Keys
+/// are made of symbols, such as bytes, nibbles or bits, that are stored packed
+/// into bytes.
 pub trait BytesKey: Display {
-    /// Create a new key from a `Vec<u8>` already in the proper representation
-    fn new(vec: Vec<u8>) -> Self;
+    /// Create a new key from symbols, one per `u8`.
+    ///
+    /// Panics if a symbol has bits set above the key's symbol width, like a
+    /// nibble of `0x10` or a bit of `2`.
+    fn new(symbols: Vec<u8>) -> Self;
 
     /// Create a new key from a `Vec<u8>` representing full bytes
     fn from_bytes(bytes: &[u8]) -> Self;
 
-    /// Get an immutable slice reference to the underlying `Vec<u8>`.
-    fn get(&self) -> &[u8];
+    /// Read full bytes as key symbols, without allocating a key
+    fn symbols(bytes: &[u8]) -> KeySymbols<'_>;
+
+    /// Get the full bytes the key represents.
+    ///
+    /// Returns `None` if the key ends in the middle of a byte, like a key
+    /// fragment of a single nibble.
+    fn to_bytes(&self) -> Option<Vec<u8>>;
+
+    /// Parse a key the way `Display` writes it, `None` if it isn't one.
+    ///
+    /// Deserializing a trie reads its key fragments with this.
+    fn from_display(fragment: &str) -> Option<Self>
+    where
+        Self: Sized;
+
+    /// Iterate over the symbols of the key
+    fn iter(&self) -> KeySymbols<'_>;
 
-    /// Get a mutable reference to the underlying `Vec<u8>`
-    fn get_mut(&mut self) -> &mut Vec<u8>;
+    /// The length of the key in symbols, such as nibbles or bits
+    fn symbol_len(&self) -> usize;
+
+    /// The symbol at `index`.
+    ///
+    /// Panics if `index` is past the end of the key, like indexing a slice.
+    fn symbol(&self, index: usize) -> u8 {
+        self.iter().get(index).expect("symbol index out of bounds")
+    }
+
+    /// Split the key at symbol `at`, returning the symbols after it
+    fn split_off(&mut self, at: usize) -> Self;
+
+    /// Append the symbols of `other` to the key
+    fn append(&mut self, other: &Self);
+
+    /// The number of symbols both keys start with
+    fn common_prefix(&self, other: &Self) -> usize {
+        self.iter()
+            .zip(other.iter())
+            .take_while(|(lhs, rhs)| lhs == rhs)
+            .count()
+    }
 
     /// Compare the shared prefix length of two keys
     fn compare(&self, other: &Self) -> KeyMatch {
-        let prefix = self
-            .get()
-            .iter()
-            .zip(other.get().iter())
-            .take_while(|(&lhs, &rhs)| lhs == rhs)
-            .count();
-
-        let self_len = self.get().len();
-        let other_len = other.get().len();
+        let prefix = self.common_prefix(other);
+        let self_len = self.symbol_len();
+        let other_len = other.symbol_len();
 
         if prefix == self_len && prefix == other_len {
             KeyMatch::Exact
@@ -77,8 +191,12 @@ pub trait BytesKey: Display {
 
 /// A "prelude" for users of the `bytes_trie` crate
 pub mod prelude {
-    pub use crate::keys::{BitKey, ByteKey, NibbleKey};
+    pub use crate::diff::Change;
+    pub use crate::entry::Entry;
+    pub use crate::fixed::{FixedTrie, Sha1Trie, Sha256Trie};
+    pub use crate::keys::{BitKey, ByteKey, NibbleKey, RadixKey};
     pub use crate::nodes::AdaptiveNode;
-    pub use crate::tries::{BitTrie, ByteTrie, NibbleTrie};
-    pub use crate::{BytesKey, BytesTrie};
+    pub use crate::tries::{BitTrie, ByteTrie, NibbleTrie, Trie};
+    pub use crate::typed::{TrieKey, TypedTrie};
+    pub use crate::{BytesKey, BytesTrie, BytesTrieExt};
 }
@@ -0,0 +1,165 @@
+//! Merging whole tries at the node level.
+//!
+//! Two nodes only need merging where their keys overlap.  Wherever a node of
+//! the other trie lands on an empty slot, or only shares part of its key with
+//! the node already there, its whole subtree is grafted in as it is, buckets
+//! and all, the same way `insert_node` places a single node.
+
+use crate::child::Child;
+use crate::keys::KeyMatch;
+use crate::{AdaptiveNode, BytesKey};
+
+impl<K: BytesKey, V> AdaptiveNode<K, V> {
+    /// Merge `other` into the node, which start at the same position.
+    ///
+    /// `path` holds the symbols of the keys above both nodes, and `resolve`
+    /// combines the values of keys that are in both, with the full key.
+    pub(crate) fn merge<F>(&mut self, mut other: Self, path: &mut Vec<u8>, resolve: &mut F)
+    where
+        F: FnMut(&K, V, V) -> V,
+    {
+        // only an empty root has neither, and it has nothing to merge
+        if other.value.is_none() && other.child.is_none() {
+            return;
+        } else if self.value.is_none() && self.child.is_none() {
+            *self = other;
+            return;
+        }
+
+        match self.key.compare(&other.key) {
+            KeyMatch::Exact => {
+                self.value = match (self.value.take(), other.value.take()) {
+                    (Some(value), Some(other)) => {
+                        let mut key = path.clone();
+                        key.extend(self.key.iter());
+                        Some(resolve(&K::new(key), value, other))
+                    }
+                    (value, other) => value.or(other),
+                };
+
+                if let Some(mut child) = other.child.take() {
+                    for node in child.get_mut().iter_mut().filter_map(Option::take) {
+                        self.merge_child(node, path, resolve);
+                    }
+                }
+            }
+
+            // the other node goes below this one
+            KeyMatch::FullSelf(idx) => {
+                other.key = other.key.split_off(idx);
+                self.merge_child(other, path, resolve);
+            }
+
+            // split this node where the other one ends, then merge them whole
+            KeyMatch::FullOther(idx) => {
+                let rest = self.replace_to(idx, None, None);
+                self.add_child_node(rest);
+                self.merge(other, path, resolve);
+            }
+
+            // nothing overlaps, both subtrees move below a new ancestor
+            KeyMatch::Partial(_) | KeyMatch::None => self.insert_node(other),
+        }
+    }
+
+    /// Merge a node into the child bucket, relative to this node's key
+    fn merge_child<F>(&mut self, mut node: Self, path: &mut Vec<u8>, resolve: &mut F)
+    where
+        F: FnMut(&K, V, V) -> V,
+    {
+        // empty keyed nodes only hold collided slots of the other bucket
+        if node.key.symbol_len() == 0 {
+            if let Some(mut child) = node.child.take() {
+                for node in child.get_mut().iter_mut().filter_map(Option::take) {
+                    self.merge_child(node, path, resolve);
+                }
+            }
+            return;
+        }
+
+        let child = self.child.get_or_insert_with(|| Child::new(1));
+        let slot = child.calculate_slot(node.key.symbol(0));
+        match child.at(slot) {
+            Some(existing) => {
+                let len = path.len();
+                path.extend(self.key.iter());
+                existing.merge(node, path, resolve);
+                path.truncate(len);
+            }
+            None => child.put(slot, node),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::test_util::{self, colliding_keys};
+    use std::collections::BTreeMap;
+
+    fn check_merge<K: BytesKey>() {
+        let mut rng = test_util::rng();
+        for round in 0..50 {
+            let lhs: BTreeMap<_, usize> = colliding_keys(&mut rng, round * 3)
+                .into_iter()
+                .zip(0..)
+                .collect();
+            let rhs: BTreeMap<_, usize> = colliding_keys(&mut rng, round * 2)
+                .into_iter()
+                .zip(1000..)
+                .collect();
+
+            let mut expected = lhs.clone();
+            for (key, value) in &rhs {
+                *expected.entry(key.clone()).or_insert(0) += value;
+            }
+
+            let trie = |map: &BTreeMap<Vec<u8>, usize>| -> Trie<K, usize> {
+                map.iter().map(|(k, &v)| (k, v)).collect()
+            };
+            let mut merged = trie(&lhs).merge(trie(&rhs), |key, a, b| {
+                let key = key.to_bytes().unwrap();
+                assert_eq!((lhs[&key], rhs[&key]), (a, b));
+                a + b
+            });
+            let entries: Vec<_> = merged
+                .iter()
+                .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+                .collect();
+            assert_eq!(entries, expected.clone().into_iter().collect::<Vec<_>>());
+
+            // the merged structure still takes removals
+            for key in expected.keys() {
+                assert_eq!(merged.remove(key), expected.get(key).copied());
+            }
+            assert!(merged.is_empty());
+        }
+    }
+
+    #[test]
+    fn merges_like_inserting() {
+        check_merge::<ByteKey>();
+        check_merge::<NibbleKey>();
+        check_merge::<BitKey>();
+    }
+
+    #[test]
+    fn append_grafts_disjoint_subtrees() {
+        let mut trie = ByteTrie::new();
+        let mut other = ByteTrie::new();
+        for i in 0..100u8 {
+            trie.insert(&[0x00, i], i as usize);
+            other.insert(&[0x01, i, i], i as usize + 100);
+            other.insert(&[0x00, i], 0);
+        }
+
+        // a value deep in a subtree trie doesn't have keeps its address
+        let deep = other.get(&[0x01, 7, 7]).unwrap() as *const usize;
+        trie.append(&mut other);
+
+        assert!(other.is_empty());
+        assert_eq!(trie.len(), 200);
+        assert_eq!(trie.get(&[0x00, 3]), Some(&0));
+        assert!(std::ptr::eq(trie.get(&[0x01, 7, 7]).unwrap(), deep));
+    }
+}
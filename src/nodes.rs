@@ -1,17 +1,19 @@
 //! `u8` based node implementations.
 
 use crate::child::{Child, MAX_CHILD_SIZE};
-use crate::keys::KeyMatch;
+use crate::cursor::{Cursor, CursorMut};
+use crate::iter::Iter;
+use crate::keys::{KeyMatch, KeySymbols};
 pub use crate::AdaptiveNode;
 use crate::BytesKey;
+use std::ops::Bound;
 
 /// The size of a child that does not exist.
 ///
 /// This is very important for `next_size`,
 /// `AdaptiveNode::smallest_ancestor_size`, and `Child::size` as careless
-/// changing of this size could cause infinite looping in
-/// `AdaptiveNode::smallest_ancestor_size`.  
-const NO_CHILD: usize = 0;
+/// changing of this size could cause infinite looping in `ancestor_size`.
+pub(crate) const NO_CHILD: usize = 0;
 
 impl<K: BytesKey, V> AdaptiveNode<K, V> {
     /// Create a new `AdaptiveNode` form a key and value
@@ -28,7 +30,7 @@ impl<K: BytesKey, V> AdaptiveNode<K, V> {
     /// This may cause the node to shrink key size, split into an empty parent,
     /// increase the child node size, or simply just add a new child.
     pub fn insert(&mut self, key: K, value: Option<V>) {
-        if self.child.is_none() && self.value.is_none() && self.key.get().is_empty() {
+        if self.child.is_none() && self.value.is_none() && self.key.symbol_len() == 0 {
             self.key = key;
             self.value = value;
         } else {
@@ -47,7 +49,7 @@ impl<K: BytesKey, V> AdaptiveNode<K, V> {
 
             // New node will be a child of current node
             KeyMatch::FullSelf(idx) => {
-                new.key = K::new(new.key.get_mut().split_off(idx));
+                new.key = new.key.split_off(idx);
                 self.add_child_node(new)
             }
 
@@ -65,6 +67,210 @@ impl<K: BytesKey, V> AdaptiveNode<K, V> {
         }
     }
 
+    /// Get a reference to the value of a key.
+    ///
+    /// The key is in key representation and includes this node's own key
+    /// fragment.
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        self.get_symbols(KeySymbols::new(key, 8))
+    }
+
+    /// Get a reference to the value of a key read from its bytes.
+    ///
+    /// The key includes this node's own key fragment.
+    pub fn get_symbols(&self, mut key: KeySymbols<'_>) -> Option<&V> {
+        if !key.strip_prefix(self.key.iter()) {
+            return None;
+        }
+
+        let next = match key.get(0) {
+            Some(next) => next,
+            None => return self.value.as_ref(),
+        };
+
+        let child = self.child.as_ref()?;
+        child.get()[child.calculate_slot(next)]
+            .as_ref()?
+            .get_symbols(key)
+    }
+
+    /// Get a mutable reference to the value of a key.
+    ///
+    /// The key is in key representation and includes this node's own key
+    /// fragment.
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        self.get_symbols_mut(KeySymbols::new(key, 8))
+    }
+
+    /// Get a mutable reference to the value of a key read from its bytes.
+    ///
+    /// The key includes this node's own key fragment.
+    pub fn get_symbols_mut(&mut self, mut key: KeySymbols<'_>) -> Option<&mut V> {
+        if !key.strip_prefix(self.key.iter()) {
+            return None;
+        }
+
+        let next = match key.get(0) {
+            Some(next) => next,
+            None => return self.value.as_mut(),
+        };
+
+        let child = self.child.as_mut()?;
+        let slot = child.calculate_slot(next);
+        child.at(slot)?.get_symbols_mut(key)
+    }
+
+    /// Remove a key from the node, returning its value if it existed.
+    ///
+    /// The key is in key representation and includes this node's own key
+    /// fragment.  Nodes left without a value are merged with their only child,
+    /// and nodes left without a value or children are removed from the parent.
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        self.remove_symbols(KeySymbols::new(key, 8))
+    }
+
+    /// Remove a key read from its bytes, returning its value if it existed.
+    ///
+    /// The key includes this node's own key fragment.
+    pub fn remove_symbols(&mut self, mut key: KeySymbols<'_>) -> Option<V> {
+        if !key.strip_prefix(self.key.iter()) {
+            return None;
+        }
+
+        let value = match key.get(0) {
+            None => self.value.take()?,
+            Some(next) => {
+                let child = self.child.as_mut()?;
+                let slot = child.calculate_slot(next);
+                let node = child.at(slot)?;
+                let value = node.remove_symbols(key)?;
+
+                if node.value.is_none() && node.child.is_none() {
+                    child.get_mut()[slot] = None;
+                }
+
+                value
+            }
+        };
+
+        self.compress();
+        Some(value)
+    }
+
+    /// Restore compression after a removal below or at this node
+    fn compress(&mut self) {
+        if self.child.as_ref().is_some_and(Child::is_empty) {
+            self.child = None;
+        }
+
+        if self.value.is_some() {
+            return;
+        }
+
+        // An empty keyed node holding collided slots merges the same way, the
+        // only node left in it lands in the same parent slot as the empty key.
+        if let Some(only) = self.child.as_mut().and_then(Child::take_only) {
+            self.key.append(&only.key);
+            self.value = only.value;
+            self.child = only.child;
+        } else if self.child.is_none() {
+            // only matters for the root, other nodes are removed by the parent
+            self.key = K::new(Vec::new());
+        }
+    }
+
+    /// Iterate over the node and its descendants in key order.
+    ///
+    /// Keys are yielded whole, starting with this node's key fragment.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(self, Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// The number of values in the node and every node below it.
+    ///
+    /// Walks every node, tries don't keep a count.
+    pub fn len(&self) -> usize {
+        let below: usize = match &self.child {
+            Some(child) => child.get().iter().flatten().map(Self::len).sum(),
+            None => 0,
+        };
+        below + self.value.is_some() as usize
+    }
+
+    /// If neither the node nor any node below it holds a value
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// A cursor positioned before the first entry of the node
+    pub fn cursor(&self) -> Cursor<'_, K, V> {
+        Cursor::new(self)
+    }
+
+    /// A mutable cursor positioned before the first entry of the node
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, K, V> {
+        CursorMut::new(self)
+    }
+
+    /// Find the node holding the subtree of keys starting with `prefix`.
+    ///
+    /// The prefix may end in the middle of the returned node's key, and the
+    /// position in the key where it ends is returned with the node.
+    pub(crate) fn prefix_node(&self, mut prefix: KeySymbols<'_>) -> Option<(&Self, usize)> {
+        if prefix.len() <= self.key.symbol_len() {
+            return if prefix.is_prefix_of(self.key.iter()) {
+                Some((self, prefix.len()))
+            } else {
+                None
+            };
+        }
+
+        if !prefix.strip_prefix(self.key.iter()) {
+            return None;
+        }
+
+        let child = self.child.as_ref()?;
+        child.get()[child.calculate_slot(prefix.get(0)?)]
+            .as_ref()?
+            .prefix_node(prefix)
+    }
+
+    /// The nodes directly below this one, sorted by their first key byte.
+    ///
+    /// Empty keyed nodes only exist to hold collided child slots, so they are
+    /// flattened into their own children.
+    pub(crate) fn children(&self) -> Vec<&Self> {
+        let mut children = match &self.child {
+            Some(child) => child
+                .get()
+                .iter()
+                .filter_map(Option::as_ref)
+                .flat_map(Self::flatten)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        children.sort_unstable_by_key(|node| node.key.symbol(0));
+        children
+    }
+
+    /// Create a flat structure of same "level" node (child) keys
+    pub(crate) fn flatten(&self) -> Vec<&Self> {
+        if self.key.symbol_len() != 0 {
+            return vec![self];
+        }
+
+        match &self.child {
+            Some(child) => child
+                .get()
+                .iter()
+                .filter_map(Option::as_ref)
+                .flat_map(Self::flatten)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
     // If we are here we know that the keys have at least `idx` byte each
     fn insert_ancestor(&mut self, mut new: Self, idx: usize) {
         let size = self.smallest_ancestor_size(&new, idx);
@@ -78,32 +284,20 @@ impl<K: BytesKey, V> AdaptiveNode<K, V> {
 
     /// Find the smallest child size for an ancestor that can fit both child hashes
     fn smallest_ancestor_size(&self, other: &Self, hash_idx: usize) -> usize {
-        let lhs = self.key.get()[hash_idx] as usize;
-        let rhs = other.key.get()[hash_idx] as usize;
-        let mut size = self.child.as_ref().map(Child::size).unwrap_or(NO_CHILD);
-
-        // `next_size` is guaranteed to not return the same number, preventing an infinite loop.
-        // we know this because of the specific sized used in `Child::size` and `next_size`.
-        // the only other setting of sizes is the line above in the `unwrap_or` where we define
-        // the default size for not having a child.
-        loop {
-            let next = next_size(size);
-            if next == MAX_CHILD_SIZE || ((lhs % next) != (rhs % next)) {
-                return next;
-            }
-
-            size = next;
-        }
+        let lhs = self.key.symbol(hash_idx);
+        let rhs = other.key.symbol(hash_idx);
+        let size = self.child.as_ref().map(Child::size).unwrap_or(NO_CHILD);
+        ancestor_size(size, lhs, rhs)
     }
 
     // We know by here that the child key has at least 1 byte
-    fn add_child_node(&mut self, child: Self) {
+    pub(crate) fn add_child_node(&mut self, child: Self) {
         if self.child.is_none() {
             self.child = Some(Child::new(1));
         }
 
         let current_child = self.child.as_mut().unwrap();
-        let slot = current_child.calculate_slot(child.key.get()[0]);
+        let slot = current_child.calculate_slot(child.key.symbol(0));
 
         match current_child.at(slot) {
             Some(existing) => existing.insert_node(child),
@@ -112,14 +306,18 @@ impl<K: BytesKey, V> AdaptiveNode<K, V> {
     }
 
     /// Shrink the `Node` to the key index and return the excess as a new node.
-    fn replace_to(&mut self, to: usize, value: Option<V>, child: Option<Child<K, V>>) -> Self {
+    pub(crate) fn replace_to(
+        &mut self,
+        to: usize,
+        value: Option<V>,
+        child: Option<Child<K, V>>,
+    ) -> Self {
         let excess = Self {
-            key: K::new(self.key.get_mut().split_off(to)),
+            key: self.key.split_off(to),
             value: self.value.take(),
             child: self.child.take(),
         };
 
-        self.key.get_mut().shrink_to_fit();
         self.value = value;
         self.child = child;
         excess
@@ -132,6 +330,24 @@ impl<K: BytesKey, V> Default for AdaptiveNode<K, V> {
     }
 }
 
+/// Find the smallest child size larger than `size` that fits both key bytes
+pub(crate) fn ancestor_size(mut size: usize, lhs: u8, rhs: u8) -> usize {
+    let (lhs, rhs) = (lhs as usize, rhs as usize);
+
+    // `next_size` is guaranteed to not return the same number, preventing an infinite loop.
+    // we know this because of the specific sized used in `Child::size` and `next_size`.
+    // the only other setting of sizes is in `smallest_ancestor_size` where we define
+    // the default size for not having a child.
+    loop {
+        let next = next_size(size);
+        if next == MAX_CHILD_SIZE || ((lhs % next) != (rhs % next)) {
+            return next;
+        }
+
+        size = next;
+    }
+}
+
 /// Find the next valid child size based on the current size
 pub(crate) fn next_size(size: usize) -> usize {
     match size {
@@ -150,6 +366,29 @@ pub(crate) fn next_size(size: usize) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::keys::ByteKey;
+    use crate::test_util;
+    use rand::prelude::*;
+    use std::collections::BTreeMap;
+
+    /// Every non-root node has a value or at least two nodes below it
+    fn assert_compressed<K: BytesKey, V>(node: &AdaptiveNode<K, V>) {
+        let nodes: Vec<_> = node
+            .child
+            .iter()
+            .flat_map(|child| child.get().iter().filter_map(Option::as_ref))
+            .collect();
+
+        for node in nodes {
+            let below = node
+                .child
+                .as_ref()
+                .map_or(0, |c| c.get().iter().filter(|n| n.is_some()).count());
+            assert!(node.value.is_some() || below >= 2);
+            assert!(node.value.is_none() || node.key.symbol_len() != 0);
+            assert_compressed(node);
+        }
+    }
 
     #[test]
     fn next_size_cannot_infinitely_loop() {
@@ -160,4 +399,30 @@ mod tests {
             size = next;
         }
     }
+
+    #[test]
+    fn remove_matches_btree() {
+        let mut rng = test_util::rng();
+        let mut node = AdaptiveNode::<ByteKey, usize>::default();
+        let mut model = BTreeMap::new();
+
+        for i in 0..2_000 {
+            let key = test_util::colliding_key(&mut rng);
+
+            if rng.gen_bool(0.5) {
+                node.insert(ByteKey::new(key.clone()), Some(i));
+                model.insert(key, i);
+            } else {
+                assert_eq!(node.remove(&key), model.remove(&key));
+            }
+
+            assert_compressed(&node);
+        }
+
+        let entries: Vec<_> = node
+            .iter()
+            .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+            .collect();
+        assert_eq!(entries, model.into_iter().collect::<Vec<_>>());
+    }
 }
@@ -0,0 +1,472 @@
+//! Persistent tries that share structure between versions.
+//!
+//! Nodes and child buckets live behind `Arc`s.  Changing a key copies only the
+//! nodes and buckets on the path down to it, every other subtree is shared
+//! with the previous version.  Keeping old versions around costs as much as
+//! the paths that changed since, and cloning a trie is a reference count bump.
+//!
+//! Bucket sizing is the same as `AdaptiveNode`'s, but buckets are plain `Arc`
+//! slices since they are copied on every write anyway.
+
+use crate::keys::{BitKey, ByteKey, KeySymbols, NibbleKey};
+use crate::nodes::{ancestor_size, NO_CHILD};
+use crate::sets::JointNode;
+use crate::BytesKey;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+
+type Bucket<K, V> = Arc<[Option<Arc<PersistentNode<K, V>>>]>;
+
+/// A node of a persistent trie, shared between versions.
+#[derive(Debug)]
+pub(crate) struct PersistentNode<K: BytesKey, V> {
+    pub(crate) key: K,
+    pub(crate) value: Option<Arc<V>>,
+    pub(crate) child: Option<Bucket<K, V>>,
+}
+
+impl<K: BytesKey + Clone, V> PersistentNode<K, V> {
+    fn leaf(key: &[u8], value: Arc<V>) -> Self {
+        Self {
+            key: K::new(key.to_vec()),
+            value: Some(value),
+            child: None,
+        }
+    }
+
+    /// A copy of the node with a different key, sharing everything below it
+    fn with_key(&self, key: K) -> Self {
+        Self {
+            key,
+            value: self.value.clone(),
+            child: self.child.clone(),
+        }
+    }
+
+    fn get(&self, mut key: KeySymbols<'_>) -> Option<&Arc<V>> {
+        if !key.strip_prefix(self.key.iter()) {
+            return None;
+        }
+
+        let next = match key.get(0) {
+            Some(next) => next,
+            None => return self.value.as_ref(),
+        };
+
+        let bucket = self.child.as_ref()?;
+        bucket[next as usize % bucket.len()].as_ref()?.get(key)
+    }
+
+    /// A new version of the node with the key inserted.
+    ///
+    /// Follows `AdaptiveNode::insert_node`, copying the nodes it changes.
+    fn insert(&self, key: &[u8], value: Arc<V>) -> Self {
+        let own = self.key.symbol_len();
+        let idx = self
+            .key
+            .iter()
+            .zip(key)
+            .take_while(|(lhs, rhs)| lhs == *rhs)
+            .count();
+
+        if idx == own && idx == key.len() {
+            Self {
+                key: self.key.clone(),
+                value: Some(value),
+                child: self.child.clone(),
+            }
+        } else if idx == own {
+            Self {
+                key: self.key.clone(),
+                value: self.value.clone(),
+                child: Some(insert_child(self.child.as_ref(), &key[idx..], value)),
+            }
+        } else if idx == key.len() {
+            let suffix = self.key.clone().split_off(idx);
+            let bucket = vec![Some(Arc::new(self.with_key(suffix)))];
+            Self {
+                key: K::new(key.to_vec()),
+                value: Some(value),
+                child: Some(bucket.into()),
+            }
+        } else {
+            let size = self.child.as_ref().map_or(NO_CHILD, |c| c.len());
+            let size = ancestor_size(size, self.key.symbol(idx), key[idx]);
+
+            let mut prefix = self.key.clone();
+            let suffix = prefix.split_off(idx);
+            let mut bucket: Vec<_> = (0..size).map(|_| None).collect();
+            let slot = suffix.symbol(0) as usize % size;
+            bucket[slot] = Some(Arc::new(self.with_key(suffix)));
+            bucket[key[idx] as usize % size] = Some(Arc::new(Self::leaf(&key[idx..], value)));
+            Self {
+                key: prefix,
+                value: None,
+                child: Some(bucket.into()),
+            }
+        }
+    }
+
+    /// A new version of the node with the key removed.
+    ///
+    /// Returns `None` if the key isn't below the node, otherwise the new node,
+    /// which is `None` itself when nothing is left in it.
+    fn remove(&self, mut key: KeySymbols<'_>) -> Option<(Option<Self>, Arc<V>)> {
+        if !key.strip_prefix(self.key.iter()) {
+            return None;
+        }
+
+        let (node, value) = if let Some(next) = key.get(0) {
+            let bucket = self.child.as_ref()?;
+            let slot = next as usize % bucket.len();
+            let (below, value) = bucket[slot].as_ref()?.remove(key)?;
+
+            let mut bucket = bucket.to_vec();
+            bucket[slot] = below.map(Arc::new);
+            let node = Self {
+                key: self.key.clone(),
+                value: self.value.clone(),
+                child: if bucket.iter().all(Option::is_none) {
+                    None
+                } else {
+                    Some(bucket.into())
+                },
+            };
+            (node, value)
+        } else {
+            let node = Self {
+                key: self.key.clone(),
+                value: None,
+                child: self.child.clone(),
+            };
+            (node, self.value.clone()?)
+        };
+
+        Some((node.compress(), value))
+    }
+
+    /// Same as `AdaptiveNode::compress`, merging a valueless node into its only child
+    fn compress(self) -> Option<Self> {
+        if self.value.is_some() {
+            return Some(self);
+        }
+
+        let mut occupied = self.child.as_ref()?.iter().flatten();
+        match (occupied.next(), occupied.next()) {
+            (Some(only), None) => {
+                let mut key = self.key.clone();
+                key.append(&only.key);
+                Some(only.with_key(key))
+            }
+            _ => Some(self),
+        }
+    }
+}
+
+impl<K: BytesKey, V> PersistentNode<K, V> {
+    /// The nodes directly below this one, sorted by their first key byte
+    pub(crate) fn children(&self) -> Vec<&Self> {
+        let mut children = match &self.child {
+            Some(bucket) => bucket.iter().flatten().flat_map(|n| n.flatten()).collect(),
+            None => Vec::new(),
+        };
+
+        children.sort_unstable_by_key(|node| node.key.symbol(0));
+        children
+    }
+
+    /// Flatten empty keyed nodes holding collided slots into their children
+    fn flatten(&self) -> Vec<&Self> {
+        if self.key.symbol_len() != 0 {
+            return vec![self];
+        }
+
+        match &self.child {
+            Some(bucket) => bucket.iter().flatten().flat_map(|n| n.flatten()).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl<K: BytesKey, V> JointNode<K> for PersistentNode<K, V> {
+    type Value = V;
+    type Entries<'a>
+        = Iter<'a, K, V>
+    where
+        Self: 'a,
+        V: 'a;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn value(&self) -> Option<&V> {
+        self.value.as_deref()
+    }
+
+    fn children(&self) -> Vec<&Self> {
+        PersistentNode::children(self)
+    }
+
+    fn entries(&self) -> Iter<'_, K, V> {
+        Iter::below(self)
+    }
+}
+
+/// A copy of `bucket` with the key inserted, creating a bucket of 1 if needed
+fn insert_child<K, V>(bucket: Option<&Bucket<K, V>>, key: &[u8], value: Arc<V>) -> Bucket<K, V>
+where
+    K: BytesKey + Clone,
+{
+    let mut bucket = bucket.map_or_else(|| vec![None], |bucket| bucket.to_vec());
+    let slot = key[0] as usize % bucket.len();
+
+    let node = match &bucket[slot] {
+        Some(existing) => existing.insert(key, value),
+        None => PersistentNode::leaf(key, value),
+    };
+
+    bucket[slot] = Some(Arc::new(node));
+    bucket.into()
+}
+
+/// A persistent trie, where every change returns a new version.
+///
+/// Versions share all the nodes that didn't change between them, so cloning
+/// is cheap and old versions stay readable for as long as they are kept.
+pub struct PersistentTrie<K: BytesKey, V> {
+    pub(crate) root: Option<Arc<PersistentNode<K, V>>>,
+}
+
+/// A persistent `u8` based Trie represented with bytes.
+pub type PersistentByteTrie<T> = PersistentTrie<ByteKey, T>;
+
+/// A persistent `u8` based Trie represented with nibbles.
+pub type PersistentNibbleTrie<T> = PersistentTrie<NibbleKey, T>;
+
+/// A persistent `u8` based Trie represented with bits.
+pub type PersistentBitTrie<T> = PersistentTrie<BitKey, T>;
+
+impl<K: BytesKey + Clone, V> PersistentTrie<K, V> {
+    /// Create an empty trie
+    pub fn new() -> Self {
+        Self { root: None }
+    }
+
+    /// A new version of the trie with the key inserted
+    pub fn insert(&self, key: &[u8], value: V) -> Self {
+        let key: Vec<u8> = K::symbols(key).collect();
+        let value = Arc::new(value);
+        let root = match &self.root {
+            Some(root) => root.insert(&key, value),
+            None => PersistentNode::leaf(&key, value),
+        };
+
+        Self {
+            root: Some(Arc::new(root)),
+        }
+    }
+
+    /// A new version of the trie without the key.
+    ///
+    /// Shares the whole trie with this version if the key wasn't in it.
+    pub fn remove(&self, key: &[u8]) -> Self {
+        match self
+            .root
+            .as_ref()
+            .and_then(|root| root.remove(K::symbols(key)))
+        {
+            Some((root, _)) => Self {
+                root: root.map(Arc::new),
+            },
+            None => self.clone(),
+        }
+    }
+
+    /// Get a reference to the value of a key
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        self.get_shared(key).map(Arc::as_ref)
+    }
+
+    /// Get the shared value of a key, which can outlive this version
+    pub(crate) fn get_shared(&self, key: &[u8]) -> Option<&Arc<V>> {
+        self.root.as_ref()?.get(K::symbols(key))
+    }
+
+    /// If the trie contains a value for the key
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+}
+
+impl<K: BytesKey, V> PersistentTrie<K, V> {
+    /// If the trie has no entries
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// If both versions are the same shared trie, without comparing entries
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(lhs), Some(rhs)) => Arc::ptr_eq(lhs, rhs),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Iterate over all entries in key order
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        match &self.root {
+            Some(root) => Iter::below(root),
+            None => Iter {
+                items: VecDeque::new(),
+            },
+        }
+    }
+}
+
+impl<K: BytesKey + Clone, V> Default for PersistentTrie<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: BytesKey, V> Clone for PersistentTrie<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<K: BytesKey + fmt::Debug, V: fmt::Debug> fmt::Debug for PersistentTrie<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+enum Item<'a, K: BytesKey, V> {
+    Node(Vec<u8>, &'a PersistentNode<K, V>),
+    Entry(Vec<u8>, &'a V),
+}
+
+/// An ordered, double-ended iterator over the entries of a persistent trie.
+///
+/// Works the same as `iter::Iter`, see its module docs.
+pub struct Iter<'a, K: BytesKey, V> {
+    items: VecDeque<Item<'a, K, V>>,
+}
+
+impl<'a, K: BytesKey, V> Iter<'a, K, V> {
+    /// Iterate over the entries below a node, with keys starting at its own
+    fn below(node: &'a PersistentNode<K, V>) -> Self {
+        let mut items = VecDeque::new();
+        items.push_back(Item::Node(node.key.iter().collect(), node));
+        Self { items }
+    }
+
+    /// Expand a node with its full key into its value and children, in key order
+    fn expand(key: Vec<u8>, node: &'a PersistentNode<K, V>) -> Vec<Item<'a, K, V>> {
+        let mut items = Vec::new();
+        for child in node.children() {
+            let mut child_key = key.clone();
+            child_key.extend(child.key.iter());
+            items.push(Item::Node(child_key, child));
+        }
+
+        if let Some(value) = &node.value {
+            items.insert(0, Item::Entry(key, value));
+        }
+
+        items
+    }
+}
+
+impl<'a, K: BytesKey, V> Iterator for Iter<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.items.pop_front()? {
+                Item::Entry(key, value) => return Some((K::new(key), value)),
+                Item::Node(key, node) => {
+                    for item in Self::expand(key, node).into_iter().rev() {
+                        self.items.push_front(item);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K: BytesKey, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.items.pop_back()? {
+                Item::Entry(key, value) => return Some((K::new(key), value)),
+                Item::Node(key, node) => self.items.extend(Self::expand(key, node)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use rand::prelude::*;
+    use std::collections::BTreeMap;
+
+    fn entries(trie: &PersistentByteTrie<usize>) -> Vec<(Vec<u8>, usize)> {
+        trie.iter()
+            .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+            .collect()
+    }
+
+    #[test]
+    fn old_versions_stay_readable() {
+        let mut rng = test_util::rng();
+        let mut versions = vec![(PersistentByteTrie::new(), BTreeMap::new())];
+
+        for i in 0..500 {
+            let key = test_util::colliding_key(&mut rng);
+            let (trie, model) = versions.last().unwrap();
+            let (mut trie, mut model) = (trie.clone(), model.clone());
+
+            if rng.gen_bool(0.6) {
+                trie = trie.insert(&key, i);
+                model.insert(key, i);
+            } else {
+                trie = trie.remove(&key);
+                model.remove(&key);
+            }
+
+            versions.push((trie, model));
+        }
+
+        for (trie, model) in versions {
+            assert_eq!(entries(&trie), model.into_iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn shares_untouched_subtrees() {
+        let trie = PersistentByteTrie::new()
+            .insert(&[0x00, 0x01], 1)
+            .insert(&[0x01, 0x01], 2)
+            .insert(&[0x01, 0x02], 3);
+        let next = trie.insert(&[0x00, 0x02], 4);
+
+        let slot = |trie: &PersistentByteTrie<usize>, slot: usize| {
+            trie.root.as_ref().unwrap().child.as_ref().unwrap()[slot]
+                .clone()
+                .unwrap()
+        };
+        assert!(Arc::ptr_eq(&slot(&trie, 1), &slot(&next, 1)));
+        assert!(!Arc::ptr_eq(&slot(&trie, 0), &slot(&next, 0)));
+        assert!(trie.remove(&[0xff]).ptr_eq(&trie));
+        assert_eq!(trie.get(&[0x00, 0x02]), None);
+        assert_eq!(next.get(&[0x00, 0x02]), Some(&4));
+    }
+}
@@ -0,0 +1,71 @@
+//! Indented tree `Display` for nodes and tries.
+//!
+//! `Debug` prints every `None` padding the child buckets, which is unreadable
+//! once a 256 slot bucket shows up.  This prints one line per node instead,
+//! with the key fragment, the value if there is one, and the child bucket size.
+//! Children are printed in key order, with the empty keyed nodes holding
+//! collided slots flattened away.
+
+use crate::tries::Trie;
+use crate::{AdaptiveNode, BytesKey};
+use std::fmt::{self, Display};
+
+impl<K: BytesKey, V: Display> AdaptiveNode<K, V> {
+    fn fmt_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let key = self.key.to_string();
+        let key = if key.is_empty() { "ε" } else { &key };
+        write!(f, "{:indent$}{}", "", key, indent = depth * 2)?;
+
+        if let Some(value) = &self.value {
+            write!(f, " = {}", value)?;
+        }
+
+        match &self.child {
+            Some(child) => {
+                writeln!(f, " ({})", child.size())?;
+                self.children()
+                    .into_iter()
+                    .try_for_each(|node| node.fmt_indented(f, depth + 1))
+            }
+            None => writeln!(f),
+        }
+    }
+}
+
+impl<K: BytesKey, V: Display> Display for AdaptiveNode<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+impl<K: BytesKey, T: Display> Display for Trie<K, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.root.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+
+    #[test]
+    fn prints_children_in_key_order() {
+        let mut trie = ByteTrie::new();
+        trie.insert(&[0x00, 0x01], "a");
+        trie.insert(&[0x80, 0x01], "b");
+        trie.insert(&[0x00, 0x02], "c");
+
+        let expected = "ε (256)\n  00 (2)\n    01 = a\n    02 = c\n  8001 = b\n";
+        assert_eq!(trie.to_string(), expected);
+    }
+
+    #[test]
+    fn bits_are_rendered_as_bits() {
+        let mut trie = BitTrie::new();
+        trie.insert(&[0b1010_0000], 1);
+        trie.insert(&[0b1011_0000], 2);
+
+        let expected = "101 (2)\n  00000 = 1\n  10000 = 2\n";
+        assert_eq!(trie.to_string(), expected);
+    }
+}
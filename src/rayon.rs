@@ -0,0 +1,270 @@
+//! Parallel construction and traversal with `rayon`.
+//!
+//! A node's child bucket is only ever replaced when the node's key shrinks,
+//! and a bucket slot only changes from keys inserted into that slot.  So once
+//! the keys stop shrinking a node, everything left splits into independent
+//! slots that can be built in parallel, recursively.  Inserting the keys up to
+//! that point in order first makes the result identical to inserting every
+//! key in order.
+
+use crate::tries::Trie;
+use crate::{AdaptiveNode, BytesKey, BytesTrie};
+use rayon::prelude::*;
+
+/// Below this many keys, inserting them is cheaper than splitting them up
+const SEQUENTIAL: usize = 1024;
+
+/// Insert into a bucket slot the same way `AdaptiveNode::add_child_node` does
+fn insert_slot<K: BytesKey, V>(slot: &mut Option<AdaptiveNode<K, V>>, key: K, value: V) {
+    let node = AdaptiveNode::new(key, Some(value));
+    match slot {
+        Some(existing) => existing.insert_node(node),
+        None => *slot = Some(node),
+    }
+}
+
+/// How many keys to insert in order before the node stops restructuring
+fn restructures_until<K: BytesKey, V>(node: Option<&AdaptiveNode<K, V>>, keys: &[(K, V)]) -> usize {
+    // the node key is always a prefix of `first`, `prefix` symbols long
+    let (first, mut prefix, mut until) = match (node, keys.first()) {
+        (Some(node), _) => (&node.key, node.key.symbol_len(), 0),
+        (None, Some((key, _))) => (key, key.symbol_len(), 1),
+        (None, None) => return 0,
+    };
+
+    for (i, (key, _)) in keys.iter().enumerate() {
+        let shared = first.common_prefix(key).min(prefix);
+        if shared < prefix {
+            prefix = shared;
+            until = i + 1;
+        }
+    }
+
+    until
+}
+
+/// Insert `keys` in order into a bucket slot, building independent slots in parallel
+fn build<K, V>(
+    mut slot: Option<AdaptiveNode<K, V>>,
+    keys: Vec<(K, V)>,
+) -> Option<AdaptiveNode<K, V>>
+where
+    K: BytesKey + Send,
+    V: Send,
+{
+    let until = restructures_until(slot.as_ref(), &keys);
+    let mut keys = keys.into_iter();
+    if keys.len() < SEQUENTIAL {
+        keys.for_each(|(key, value)| insert_slot(&mut slot, key, value));
+        return slot;
+    }
+
+    keys.by_ref()
+        .take(until)
+        .for_each(|(key, value)| insert_slot(&mut slot, key, value));
+
+    // the node can still create its first bucket, which is always of size 1
+    let mut node = slot.expect("at least one key was inserted");
+    while node.child.is_none() {
+        match keys.next() {
+            Some((key, value)) => node.insert_node(AdaptiveNode::new(key, Some(value))),
+            None => return Some(node),
+        }
+    }
+
+    let child = node.child.as_mut().expect("bucket was just created");
+    let prefix = node.key.symbol_len();
+    let mut slots: Vec<Vec<(K, V)>> = (0..child.size()).map(|_| Vec::new()).collect();
+    for (mut key, value) in keys {
+        if key.symbol_len() == prefix {
+            node.value = Some(value);
+        } else {
+            let rest = key.split_off(prefix);
+            slots[child.calculate_slot(rest.symbol(0))].push((rest, value));
+        }
+    }
+
+    let work: Vec<_> = slots
+        .into_iter()
+        .enumerate()
+        .filter(|(_, keys)| !keys.is_empty())
+        .map(|(slot, keys)| (slot, child.get_mut()[slot].take(), keys))
+        .collect();
+
+    let built: Vec<_> = work
+        .into_par_iter()
+        .map(|(slot, node, keys)| (slot, build(node, keys)))
+        .collect();
+
+    for (slot, node) in built {
+        child.get_mut()[slot] = node;
+    }
+
+    Some(node)
+}
+
+/// Insert `keys` in order into `root`, using the same structure as sequential insertion
+fn par_extend_root<K, V, B, I>(root: &mut AdaptiveNode<K, V>, keys: I)
+where
+    K: BytesKey + Send,
+    V: Send,
+    B: AsRef<[u8]> + Send,
+    I: IntoParallelIterator<Item = (B, V)>,
+{
+    let keys: Vec<(K, V)> = keys
+        .into_par_iter()
+        .map(|(key, value)| (K::from_bytes(key.as_ref()), value))
+        .collect();
+
+    let empty = root.value.is_none() && root.child.is_none() && root.key.symbol_len() == 0;
+    let slot = if empty {
+        None
+    } else {
+        Some(std::mem::take(root))
+    };
+
+    if let Some(node) = build(slot, keys) {
+        *root = node;
+    }
+}
+
+/// Visit every entry below `root` in parallel, in no particular order
+fn par_iter_root<K, V>(root: &AdaptiveNode<K, V>) -> impl ParallelIterator<Item = (K, &V)>
+where
+    K: BytesKey + Send + Sync,
+    V: Sync,
+{
+    let target = rayon::current_num_threads() * 4;
+    let mut entries = Vec::new();
+    let mut frontier = vec![(Vec::new(), root)];
+
+    // split the top of the trie into enough subtrees to keep every thread busy
+    while frontier.len() < target && frontier.iter().any(|(_, node)| node.child.is_some()) {
+        let mut next = Vec::new();
+        for (prefix, node) in frontier {
+            let mut key = prefix;
+            key.extend(node.key.iter());
+
+            if let Some(value) = &node.value {
+                entries.push((K::new(key.clone()), value));
+            }
+
+            next.extend(
+                node.children()
+                    .into_iter()
+                    .map(|child| (key.clone(), child)),
+            );
+        }
+        frontier = next;
+    }
+
+    entries
+        .into_par_iter()
+        .chain(frontier.into_par_iter().flat_map_iter(|(prefix, node)| {
+            node.iter().map(move |(key, value)| {
+                let mut full = prefix.clone();
+                full.extend(key.iter());
+                (K::new(full), value)
+            })
+        }))
+}
+
+impl<K: BytesKey + Send, T: Send> Trie<K, T> {
+    /// Build a trie in parallel, identical to inserting every key in order
+    pub fn par_from_iter<B, I>(keys: I) -> Self
+    where
+        B: AsRef<[u8]> + Send,
+        I: IntoParallelIterator<Item = (B, T)>,
+    {
+        let mut trie = Self::new();
+        par_extend_root(&mut trie.root, keys);
+        trie
+    }
+}
+
+impl<K: BytesKey + Send + Sync, T: Sync> Trie<K, T> {
+    /// Visit every entry in parallel, in no particular order
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = (K, &T)> {
+        par_iter_root(&self.root)
+    }
+}
+
+impl<B, K, T> ParallelExtend<(B, T)> for Trie<K, T>
+where
+    B: AsRef<[u8]> + Send,
+    K: BytesKey + Send,
+    T: Send,
+{
+    /// Insert keys in parallel, identical to inserting every key in order
+    fn par_extend<I>(&mut self, keys: I)
+    where
+        I: IntoParallelIterator<Item = (B, T)>,
+    {
+        par_extend_root(&mut self.root, keys)
+    }
+}
+
+impl<B, K, T> FromParallelIterator<(B, T)> for Trie<K, T>
+where
+    B: AsRef<[u8]> + Send,
+    K: BytesKey + Send,
+    T: Send,
+{
+    fn from_par_iter<I>(keys: I) -> Self
+    where
+        I: IntoParallelIterator<Item = (B, T)>,
+    {
+        Self::par_from_iter(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use crate::tries::{ByteTrie, NibbleTrie};
+    use rand::prelude::*;
+
+    fn oids(count: usize) -> Vec<(Vec<u8>, usize)> {
+        let mut rng = test_util::rng();
+        (0..count)
+            .map(|i| {
+                let len = rng.gen_range(0, 21);
+                let oid = (0..len).map(|_| rng.gen()).collect();
+                (oid, i)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_structure_as_sequential() {
+        let mut oids = oids(20_000);
+        oids.extend(oids.clone().into_iter().step_by(7).map(|(k, v)| (k, v + 1)));
+
+        let sequential: ByteTrie<_> = oids.iter().cloned().collect();
+        let parallel = ByteTrie::par_from_iter(oids.clone());
+        assert_eq!(format!("{:?}", parallel), format!("{:?}", sequential));
+
+        let (head, tail) = oids.split_at(5_000);
+        let mut extended = NibbleTrie::par_from_iter(head.to_vec());
+        extended.par_extend(tail.to_vec());
+        let sequential: NibbleTrie<_> = oids.into_iter().collect();
+        assert_eq!(format!("{:?}", extended), format!("{:?}", sequential));
+    }
+
+    #[test]
+    fn visits_every_entry() {
+        let trie: ByteTrie<_> = oids(5_000).into_iter().collect();
+        let mut parallel: Vec<_> = trie
+            .par_iter()
+            .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+            .collect();
+        parallel.sort();
+
+        let sequential: Vec<_> = trie
+            .iter()
+            .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+            .collect();
+        assert_eq!(parallel, sequential);
+    }
+}
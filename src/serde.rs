@@ -1,29 +1,59 @@
+//! Tries serialize as nested maps of key fragments, in the shape of the
+//! nodes.  Fragments are written with the key's `Display`, which is hex for
+//! the radix keys, and read back with `BytesKey::from_display`.  A node with children maps its fragment to a map of them, and a
+//! node without maps it straight to its value.  The value of a node that has
+//! both is kept under the empty fragment in its map, which is also where the
+//! value of the empty key goes at the top level.
+//!
+//! Deserializing has to tell a map of children from a value, so it needs a
+//! self-describing format, and values that serialize as maps can only be read
+//! back from under an empty fragment.  The input is untrusted: fragments the
+//! key type doesn't parse, keys that don't end on a whole byte and keys given
+//! twice are errors.
+
 use crate::child::Child;
-use crate::keys::BytesKey;
+use crate::keys::{BytesKey, RadixKey};
 use crate::nodes::AdaptiveNode;
-use crate::tries::{BitTrie, ByteTrie, NibbleTrie};
+use crate::tries::Trie;
+use crate::BytesTrie;
+use serde::de::value::{
+    BorrowedBytesDeserializer, BorrowedStrDeserializer, EnumAccessDeserializer,
+    SeqAccessDeserializer, UnitDeserializer,
+};
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, Unexpected, Visitor};
 use serde::ser::SerializeMap;
-use serde::{Serialize, Serializer};
-
-macro_rules! impl_serialize_root {
-    ($trie:ty, $name: expr) => {
-        impl<T> Serialize for $trie
-        where
-            T: Serialize,
-        {
-            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-            where
-                S: Serializer,
-            {
-                serializer.serialize_newtype_struct($name, &self.root)
-            }
-        }
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Key types of tries that serialize, naming the trie's newtype struct
+pub trait SerdeKey: BytesKey {
+    /// The name a trie of these keys serializes under
+    const TRIE_NAME: &'static str = "Trie";
+}
+
+/// Byte, nibble and bit tries keep the names they had before `Trie`
+impl<const BITS: usize> SerdeKey for RadixKey<BITS> {
+    const TRIE_NAME: &'static str = match BITS {
+        8 => "ByteTrie",
+        4 => "NibbleTrie",
+        1 => "BitTrie",
+        _ => "Trie",
     };
 }
 
-impl_serialize_root!(ByteTrie<T>, "ByteTrie");
-impl_serialize_root!(NibbleTrie<T>, "NibbleTrie");
-impl_serialize_root!(BitTrie<T>, "BitTrie");
+impl<K, T> Serialize for Trie<K, T>
+where
+    K: SerdeKey,
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(K::TRIE_NAME, &self.root)
+    }
+}
 
 impl<K, T> Serialize for AdaptiveNode<K, T>
 where
@@ -36,7 +66,14 @@ where
     {
         let mut map = serializer.serialize_map(None)?;
 
-        flatten_node(&self)
+        // an empty keyed root flattens into its children, keep its own value
+        if self.key.symbol_len() == 0 {
+            if let Some(value) = &self.value {
+                map.serialize_entry("", value)?;
+            }
+        }
+
+        self.flatten()
             .iter()
             .try_for_each(|n| node_or_children(n, &mut map))?;
 
@@ -59,29 +96,37 @@ where
     if is_empty {
         map.serialize_entry(&node.key.to_string(), &node.value)
     } else {
-        map.serialize_entry(&node.key.to_string(), &node.child)
+        map.serialize_entry(&node.key.to_string(), &Branch(node))
     }
 }
 
-/// Create a flat structure of same "level" node (child) keys
-fn flatten_node<K, T>(node: &AdaptiveNode<K, T>) -> Vec<&AdaptiveNode<K, T>>
+/// A node with children, serialized as its value and children in one map
+struct Branch<'a, K: BytesKey, T>(&'a AdaptiveNode<K, T>);
+
+impl<'a, K, T> Serialize for Branch<'a, K, T>
 where
     K: BytesKey,
     T: Serialize,
 {
-    if !node.key.get().is_empty() {
-        return vec![node];
-    }
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+
+        if let Some(value) = &self.0.value {
+            map.serialize_entry("", value)?;
+        }
 
-    match &node.child {
-        Some(child) => child.get().iter().fold(Vec::new(), |mut acc, child| {
-            if let Some(child) = child {
-                acc.append(&mut flatten_node(child));
+        if let Some(child) = &self.0.child {
+            for nodes in child.get().iter().flatten().map(AdaptiveNode::flatten) {
+                nodes
+                    .iter()
+                    .try_for_each(|node| node_or_children(node, &mut map))?;
             }
+        }
 
-            acc
-        }),
-        None => Vec::new(),
+        map.end()
     }
 }
 
@@ -98,7 +143,7 @@ where
 
         self.get()
             .iter()
-            .filter_map(|child| child.as_ref().map(flatten_node))
+            .filter_map(|child| child.as_ref().map(AdaptiveNode::flatten))
             .try_for_each(|nodes| {
                 nodes
                     .iter()
@@ -108,3 +153,363 @@ where
         map.end()
     }
 }
+
+impl<'de, K, T> Deserialize<'de> for Trie<K, T>
+where
+    K: SerdeKey,
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(K::TRIE_NAME, TrieVisitor(PhantomData))
+    }
+}
+
+struct TrieVisitor<K, T>(PhantomData<(K, T)>);
+
+impl<'de, K, T> Visitor<'de> for TrieVisitor<K, T>
+where
+    K: SerdeKey,
+    T: Deserialize<'de>,
+{
+    type Value = Trie<K, T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of key fragments")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut trie = Trie::new();
+        Nodes {
+            trie: &mut trie,
+            path: &mut Vec::new(),
+        }
+        .visit_map(map)?;
+        Ok(trie)
+    }
+}
+
+/// The entries of a map of fragments, below the symbols in `path`
+struct Nodes<'a, K: BytesKey, T> {
+    trie: &'a mut Trie<K, T>,
+    path: &'a mut Vec<u8>,
+}
+
+impl<'a, 'de, K: BytesKey, T> Nodes<'a, K, T>
+where
+    T: Deserialize<'de>,
+{
+    /// Insert the value of the key in `path`
+    fn insert<E: de::Error>(&mut self, value: T) -> Result<(), E> {
+        let key = K::new(self.path.clone());
+        if key.to_bytes().is_none() {
+            return Err(E::custom(format_args!("key {} isn't whole bytes", key)));
+        } else if self.trie.root.get_symbols(key.iter()).is_some() {
+            return Err(E::custom(format_args!("duplicate key {}", key)));
+        }
+
+        self.trie.insert_key(key, value);
+        Ok(())
+    }
+
+    /// Insert a value read from `deserializer`
+    fn insert_from<D>(mut self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = T::deserialize(deserializer)?;
+        self.insert(value)
+    }
+}
+
+impl<'a, 'de, K: BytesKey, T> Visitor<'de> for Nodes<'a, K, T>
+where
+    T: Deserialize<'de>,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of key fragments")
+    }
+
+    fn visit_map<A>(mut self, mut map: A) -> Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        while let Some(fragment) = map.next_key::<String>()? {
+            let symbols = K::from_display(&fragment).ok_or_else(|| {
+                de::Error::invalid_value(Unexpected::Str(&fragment), &"a key fragment")
+            })?;
+
+            let len = self.path.len();
+            self.path.extend(symbols.iter());
+            if fragment.is_empty() {
+                let value = map.next_value()?;
+                self.insert(value)?;
+            } else {
+                map.next_value_seed(Nodes {
+                    trie: &mut *self.trie,
+                    path: &mut *self.path,
+                })?;
+            }
+            self.path.truncate(len);
+        }
+
+        Ok(())
+    }
+}
+
+/// What a fragment maps to, which is either a map of children or a value
+impl<'a, 'de, K: BytesKey, T> DeserializeSeed<'de> for Nodes<'a, K, T>
+where
+    T: Deserialize<'de>,
+{
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(Slot(self))
+    }
+}
+
+/// Reads a map as children and anything else as the value of the path
+struct Slot<'a, K: BytesKey, T>(Nodes<'a, K, T>);
+
+macro_rules! forward_values {
+    ($($method:ident($ty:ty)),*) => {
+        $(
+            fn $method<E: de::Error>(self, value: $ty) -> Result<(), E> {
+                self.0.insert_from(value.into_deserializer())
+            }
+        )*
+    };
+}
+
+impl<'a, 'de, K: BytesKey, T> Visitor<'de> for Slot<'a, K, T>
+where
+    T: Deserialize<'de>,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of key fragments or a value")
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.0.visit_map(map)
+    }
+
+    forward_values!(
+        visit_bool(bool),
+        visit_i64(i64),
+        visit_i128(i128),
+        visit_u64(u64),
+        visit_u128(u128),
+        visit_f64(f64),
+        visit_char(char),
+        visit_string(String),
+        visit_byte_buf(Vec<u8>)
+    );
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<(), E> {
+        self.0.insert_from(value.into_deserializer())
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, value: &'de str) -> Result<(), E> {
+        self.0.insert_from(BorrowedStrDeserializer::new(value))
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<(), E> {
+        self.0.insert_from(value.into_deserializer())
+    }
+
+    fn visit_borrowed_bytes<E: de::Error>(self, value: &'de [u8]) -> Result<(), E> {
+        self.0.insert_from(BorrowedBytesDeserializer::new(value))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<(), E> {
+        self.0.insert_from(UnitDeserializer::new())
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        self.0.insert_from(UnitDeserializer::new())
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0.insert_from(deserializer)
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.0.insert_from(deserializer)
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<(), A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        self.0.insert_from(SeqAccessDeserializer::new(seq))
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<(), A::Error>
+    where
+        A: de::EnumAccess<'de>,
+    {
+        self.0.insert_from(EnumAccessDeserializer::new(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SerdeKey;
+    use crate::keys::KeySymbols;
+    use crate::prelude::*;
+    use crate::test_util;
+    use std::collections::BTreeMap;
+    use std::fmt;
+
+    /// A key of bytes written as decimal numbers, like `97.98`
+    #[derive(Debug, Clone)]
+    struct Dotted(ByteKey);
+
+    impl fmt::Display for Dotted {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let symbols: Vec<_> = self.0.iter().map(|symbol| symbol.to_string()).collect();
+            f.write_str(&symbols.join("."))
+        }
+    }
+
+    impl BytesKey for Dotted {
+        fn new(symbols: Vec<u8>) -> Self {
+            Dotted(ByteKey::new(symbols))
+        }
+
+        fn from_bytes(bytes: &[u8]) -> Self {
+            Dotted(ByteKey::from_bytes(bytes))
+        }
+
+        fn symbols(bytes: &[u8]) -> KeySymbols<'_> {
+            ByteKey::symbols(bytes)
+        }
+
+        fn to_bytes(&self) -> Option<Vec<u8>> {
+            self.0.to_bytes()
+        }
+
+        fn from_display(fragment: &str) -> Option<Self> {
+            if fragment.is_empty() {
+                return Some(Self::new(Vec::new()));
+            }
+            let symbols: Option<Vec<u8>> = fragment.split('.').map(|n| n.parse().ok()).collect();
+            symbols.map(Self::new)
+        }
+
+        fn iter(&self) -> KeySymbols<'_> {
+            self.0.iter()
+        }
+
+        fn symbol_len(&self) -> usize {
+            self.0.symbol_len()
+        }
+
+        fn split_off(&mut self, at: usize) -> Self {
+            Dotted(self.0.split_off(at))
+        }
+
+        fn append(&mut self, other: &Self) {
+            self.0.append(&other.0)
+        }
+    }
+
+    impl SerdeKey for Dotted {}
+
+    fn round_trip<K: BytesKey>()
+    where
+        Trie<K, usize>: serde::Serialize + serde::de::DeserializeOwned,
+    {
+        let mut rng = test_util::rng();
+        let model: BTreeMap<_, usize> = test_util::colliding_keys(&mut rng, 300)
+            .into_iter()
+            .zip(0..)
+            .collect();
+        let trie: Trie<K, usize> = model.iter().map(|(k, &v)| (k, v)).collect();
+
+        let json = serde_json::to_string(&trie).unwrap();
+        let read: Trie<K, usize> = serde_json::from_str(&json).unwrap();
+        let entries: Vec<_> = read
+            .iter()
+            .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+            .collect();
+        assert_eq!(entries, model.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn round_trips_values_above_children() {
+        round_trip::<ByteKey>();
+        round_trip::<NibbleKey>();
+        round_trip::<BitKey>();
+        round_trip::<RadixKey<5>>();
+        round_trip::<Dotted>();
+
+        let trie: ByteTrie<_> = vec![(&b""[..], 0), (b"a", 1), (b"ab", 2), (b"ac", 3)]
+            .into_iter()
+            .collect();
+        let json = serde_json::to_string(&trie).unwrap();
+        assert_eq!(json, r#"{"":0,"61":{"":1,"62":2,"63":3}}"#);
+
+        // formats that keep struct names still see the names from before `Trie`
+        assert_eq!(ByteKey::TRIE_NAME, "ByteTrie");
+        assert_eq!(NibbleKey::TRIE_NAME, "NibbleTrie");
+        assert_eq!(BitKey::TRIE_NAME, "BitTrie");
+        assert_eq!(RadixKey::<5>::TRIE_NAME, "Trie");
+    }
+
+    #[test]
+    fn reads_fragments_of_custom_keys() {
+        let trie: Trie<Dotted, _> = vec![(&b"a"[..], 1), (b"ab", 2), (b"ac", 3)]
+            .into_iter()
+            .collect();
+        let json = serde_json::to_string(&trie).unwrap();
+        assert_eq!(json, r#"{"97":{"":1,"98":2,"99":3}}"#);
+
+        let read: Trie<Dotted, usize> = serde_json::from_str(r#"{"97.98":{"":2,"99":3}}"#).unwrap();
+        assert_eq!(read.get(b"ab"), Some(&2));
+        assert_eq!(read.get(b"abc"), Some(&3));
+        assert!(serde_json::from_str::<Trie<Dotted, usize>>(r#"{"61":1,"256":2}"#).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_dumps() {
+        let read = |json| serde_json::from_str::<NibbleTrie<usize>>(json).map(|t| t.len());
+        assert_eq!(read(r#"{"6":{"1":0,"2":{"":1,"f0":2}}}"#).unwrap(), 3);
+
+        // not hex, a key of half a byte, and the same key twice
+        assert!(read(r#"{"6x":0}"#).is_err());
+        assert!(read(r#"{"612":0}"#).is_err());
+        assert!(read(r#"{"61":0,"6":{"1":1}}"#).is_err());
+        assert!(serde_json::from_str::<ByteTrie<usize>>(r#"{"6":0}"#).is_err());
+        assert!(serde_json::from_str::<BitTrie<usize>>(r#"{"2":0}"#).is_err());
+    }
+}
@@ -0,0 +1,431 @@
+//! Set operations between two tries, walking both of them together.
+//!
+//! The two tries compress their keys differently, so the walk keeps a
+//! position in each trie, a node and how many symbols of its key fragment
+//! are behind it.  Wherever only one of the tries has keys below a position,
+//! the whole subtree is either iterated or skipped without looking at the
+//! other trie again.  The walk only needs a node's key, value and children,
+//! so it works the same over persistent tries, see `JointNode`.
+
+use crate::tries::Trie;
+use crate::{AdaptiveNode, BytesKey};
+use std::collections::VecDeque;
+
+/// The parts of a node walking two tries together needs
+pub(crate) trait JointNode<K: BytesKey> {
+    type Value;
+    type Entries<'a>: Iterator<Item = (K, &'a Self::Value)>
+    where
+        Self: 'a,
+        Self::Value: 'a;
+
+    fn key(&self) -> &K;
+    fn value(&self) -> Option<&Self::Value>;
+    /// The nodes directly below, sorted by their first symbol
+    fn children(&self) -> Vec<&Self>;
+    /// All entries of the subtree, with keys starting at the node's own key
+    fn entries(&self) -> Self::Entries<'_>;
+}
+
+impl<K: BytesKey, V> JointNode<K> for AdaptiveNode<K, V> {
+    type Value = V;
+    type Entries<'a>
+        = crate::iter::Iter<'a, K, V>
+    where
+        Self: 'a,
+        V: 'a;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn value(&self) -> Option<&V> {
+        self.value.as_ref()
+    }
+
+    fn children(&self) -> Vec<&Self> {
+        AdaptiveNode::children(self)
+    }
+
+    fn entries(&self) -> Self::Entries<'_> {
+        self.iter()
+    }
+}
+
+/// A position inside a node, after `usize` symbols of its key fragment
+type Position<'a, N> = (&'a N, usize);
+
+/// Which of the tries have a key
+pub(crate) enum Found<'a, A, B> {
+    Left(&'a A),
+    Right(&'a B),
+    Both(&'a A, &'a B),
+}
+
+enum Item<'a, K: BytesKey, L: JointNode<K> + 'a, R: JointNode<K> + 'a> {
+    /// Positions in both tries with the same symbols before them
+    Both(Vec<u8>, Position<'a, L>, Position<'a, R>),
+    /// A subtree only the left trie has, with the symbols before its node
+    Left(Vec<u8>, L::Entries<'a>),
+    /// A subtree only the right trie has, with the symbols before its node
+    Right(Vec<u8>, R::Entries<'a>),
+    /// A key ready to be yielded
+    Entry(Vec<u8>, Found<'a, L::Value, R::Value>),
+}
+
+/// Walk two tries together in key order, yielding the keys of the wanted kinds
+pub(crate) struct Joint<'a, K: BytesKey, L: JointNode<K> + 'a, R: JointNode<K> + 'a> {
+    items: VecDeque<Item<'a, K, L, R>>,
+    left: bool,
+    right: bool,
+    both: bool,
+    shared: bool,
+}
+
+impl<'a, K: BytesKey, L: JointNode<K>, R: JointNode<K>> Joint<'a, K, L, R> {
+    /// Yield keys only in `lhs`, only in `rhs` or in both, as wanted
+    pub(crate) fn new(
+        lhs: Option<&'a L>,
+        rhs: Option<&'a R>,
+        left: bool,
+        right: bool,
+        both: bool,
+    ) -> Self {
+        let item = match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) => Some(Item::Both(Vec::new(), (lhs, 0), (rhs, 0))),
+            (Some(lhs), None) if left => Some(Item::Left(Vec::new(), lhs.entries())),
+            (None, Some(rhs)) if right => Some(Item::Right(Vec::new(), rhs.entries())),
+            _ => None,
+        };
+        Self {
+            items: item.into_iter().collect(),
+            left,
+            right,
+            both,
+            shared: false,
+        }
+    }
+
+    /// Skip the subtrees both tries share, which only holds keys in both
+    pub(crate) fn skip_shared(mut self) -> Self {
+        self.shared = true;
+        self
+    }
+
+    /// Split both positions where their keys end or differ, in key order
+    fn expand(
+        &self,
+        mut path: Vec<u8>,
+        (lhs, lhs_at): Position<'a, L>,
+        (rhs, rhs_at): Position<'a, R>,
+    ) -> Vec<Item<'a, K, L, R>> {
+        if self.shared
+            && lhs_at == rhs_at
+            && std::ptr::eq(lhs as *const L as *const (), rhs as *const R as *const ())
+        {
+            return Vec::new();
+        }
+
+        let common = lhs
+            .key()
+            .iter()
+            .skip(lhs_at)
+            .zip(rhs.key().iter().skip(rhs_at))
+            .take_while(|(l, r)| l == r)
+            .count();
+        path.extend(lhs.key().iter().skip(lhs_at).take(common));
+        let (lhs_at, rhs_at) = (lhs_at + common, rhs_at + common);
+
+        // a node whose key ends here continues with its children
+        let (lhs_value, lhs_next) = next_positions(lhs, lhs_at);
+        let (rhs_value, rhs_next) = next_positions(rhs, rhs_at);
+
+        let mut items = Vec::new();
+        let found = match (lhs_value, rhs_value) {
+            (Some(l), Some(r)) if self.both => Some(Found::Both(l, r)),
+            (Some(l), None) if self.left => Some(Found::Left(l)),
+            (None, Some(r)) if self.right => Some(Found::Right(r)),
+            _ => None,
+        };
+        if let Some(found) = found {
+            items.push(Item::Entry(path.clone(), found));
+        }
+
+        let mut lhs_next = lhs_next.into_iter().peekable();
+        let mut rhs_next = rhs_next.into_iter().peekable();
+        loop {
+            let lhs_symbol = lhs_next.peek().map(|(node, at)| node.key().symbol(*at));
+            let rhs_symbol = rhs_next.peek().map(|(node, at)| node.key().symbol(*at));
+            let item = match (lhs_symbol, rhs_symbol) {
+                (None, None) => break,
+                (Some(l), Some(r)) if l == r => {
+                    let (lhs, rhs) = (lhs_next.next().unwrap(), rhs_next.next().unwrap());
+                    Some(Item::Both(path.clone(), lhs, rhs))
+                }
+                (Some(l), r) if r.is_none_or(|r| l < r) => {
+                    let (node, at) = lhs_next.next().unwrap();
+                    let prefix = path[..path.len() - at].to_vec();
+                    self.left.then(|| Item::Left(prefix, node.entries()))
+                }
+                _ => {
+                    let (node, at) = rhs_next.next().unwrap();
+                    let prefix = path[..path.len() - at].to_vec();
+                    self.right.then(|| Item::Right(prefix, node.entries()))
+                }
+            };
+            items.extend(item);
+        }
+
+        items
+    }
+}
+
+/// The value at a position and the positions following it, in key order
+fn next_positions<K: BytesKey, N: JointNode<K>>(
+    node: &N,
+    at: usize,
+) -> (Option<&N::Value>, Vec<Position<'_, N>>) {
+    if at < node.key().symbol_len() {
+        (None, vec![(node, at)])
+    } else {
+        let children = node
+            .children()
+            .into_iter()
+            .map(|child| (child, 0))
+            .collect();
+        (node.value(), children)
+    }
+}
+
+/// Prepend the symbols before a subtree to a key of the subtree
+fn full_key<K: BytesKey>(prefix: &[u8], key: K) -> Vec<u8> {
+    let mut full = prefix.to_vec();
+    full.extend(key.iter());
+    full
+}
+
+impl<'a, K: BytesKey, L: JointNode<K>, R: JointNode<K>> Iterator for Joint<'a, K, L, R> {
+    type Item = (Vec<u8>, Found<'a, L::Value, R::Value>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.items.front_mut()? {
+                Item::Left(prefix, iter) => match iter.next() {
+                    Some((key, value)) => return Some((full_key(prefix, key), Found::Left(value))),
+                    None => {
+                        self.items.pop_front();
+                    }
+                },
+                Item::Right(prefix, iter) => match iter.next() {
+                    Some((key, value)) => {
+                        return Some((full_key(prefix, key), Found::Right(value)))
+                    }
+                    None => {
+                        self.items.pop_front();
+                    }
+                },
+                _ => match self.items.pop_front()? {
+                    Item::Entry(key, found) => return Some((key, found)),
+                    Item::Both(path, lhs, rhs) => {
+                        for item in self.expand(path, lhs, rhs).into_iter().rev() {
+                            self.items.push_front(item);
+                        }
+                    }
+                    _ => unreachable!("subtrees are handled in place"),
+                },
+            }
+        }
+    }
+}
+
+/// The keys in both of two tries with both their values, in key order
+pub struct Intersection<'a, K: BytesKey, A, B> {
+    joint: Joint<'a, K, AdaptiveNode<K, A>, AdaptiveNode<K, B>>,
+}
+
+impl<'a, K: BytesKey, A, B> Iterator for Intersection<'a, K, A, B> {
+    type Item = (K, &'a A, &'a B);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.joint.next()? {
+            (key, Found::Both(value, other)) => Some((K::new(key), value, other)),
+            _ => unreachable!("intersections only walk keys in both tries"),
+        }
+    }
+}
+
+/// The entries of a trie whose keys are not in another trie, in key order
+pub struct Difference<'a, K: BytesKey, A, B> {
+    joint: Joint<'a, K, AdaptiveNode<K, A>, AdaptiveNode<K, B>>,
+}
+
+impl<'a, K: BytesKey, A, B> Iterator for Difference<'a, K, A, B> {
+    type Item = (K, &'a A);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.joint.next()? {
+            (key, Found::Left(value)) => Some((K::new(key), value)),
+            _ => unreachable!("differences only walk keys in the left trie"),
+        }
+    }
+}
+
+/// The entries of two tries whose keys are in only one of them, in key order
+pub struct SymmetricDifference<'a, K: BytesKey, T> {
+    joint: Joint<'a, K, AdaptiveNode<K, T>, AdaptiveNode<K, T>>,
+}
+
+impl<'a, K: BytesKey, T> Iterator for SymmetricDifference<'a, K, T> {
+    type Item = (K, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.joint.next()? {
+            (key, Found::Left(value)) | (key, Found::Right(value)) => Some((K::new(key), value)),
+            _ => unreachable!("symmetric differences skip keys in both tries"),
+        }
+    }
+}
+
+impl<K: BytesKey, T> Trie<K, T> {
+    /// The keys also in `other` with the values of both tries, in key order.
+    ///
+    /// Subtrees only one of the tries has are skipped.
+    pub fn intersection<'a, U>(&'a self, other: &'a Trie<K, U>) -> Intersection<'a, K, T, U> {
+        Intersection {
+            joint: Joint::new(Some(&self.root), Some(&other.root), false, false, true),
+        }
+    }
+
+    /// The entries whose keys are not in `other`, in key order.
+    ///
+    /// Subtrees only `other` has are skipped.
+    pub fn difference<'a, U>(&'a self, other: &'a Trie<K, U>) -> Difference<'a, K, T, U> {
+        Difference {
+            joint: Joint::new(Some(&self.root), Some(&other.root), true, false, false),
+        }
+    }
+
+    /// The entries of both tries whose keys are in only one of them, in key
+    /// order
+    pub fn symmetric_difference<'a>(&'a self, other: &'a Self) -> SymmetricDifference<'a, K, T> {
+        SymmetricDifference {
+            joint: Joint::new(Some(&self.root), Some(&other.root), true, true, false),
+        }
+    }
+
+    /// Remove every key that is not in `other`
+    pub fn retain_keys_in<U>(&mut self, other: &Trie<K, U>) {
+        let keys: Vec<K> = self.difference(other).map(|(key, _)| key).collect();
+        for key in keys {
+            self.root.remove_symbols(key.iter());
+        }
+    }
+
+    /// Remove every key that is also in `other`
+    pub fn remove_keys_in<U>(&mut self, other: &Trie<K, U>) {
+        let keys: Vec<K> = self.intersection(other).map(|(key, _, _)| key).collect();
+        for key in keys {
+            self.root.remove_symbols(key.iter());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::test_util::{self, colliding_keys};
+    use std::collections::BTreeMap;
+
+    fn check_sets<K: BytesKey>() {
+        let mut rng = test_util::rng();
+        for round in 0..50 {
+            let lhs: BTreeMap<_, usize> = colliding_keys(&mut rng, round * 3)
+                .into_iter()
+                .zip(0..)
+                .collect();
+            let rhs: BTreeMap<_, usize> = colliding_keys(&mut rng, round * 2)
+                .into_iter()
+                .zip(1000..)
+                .collect();
+            let trie = |map: &BTreeMap<Vec<u8>, usize>| -> Trie<K, usize> {
+                map.iter().map(|(k, &v)| (k, v)).collect()
+            };
+            let (left, right) = (trie(&lhs), trie(&rhs));
+
+            let entries = |iter: &mut dyn Iterator<Item = (K, &usize)>| -> Vec<_> {
+                iter.map(|(k, &v)| (k.to_bytes().unwrap(), v)).collect()
+            };
+            let only = |map: &BTreeMap<Vec<u8>, usize>, other: &BTreeMap<Vec<u8>, usize>| {
+                map.iter()
+                    .filter(|(k, _)| !other.contains_key(*k))
+                    .map(|(k, &v)| (k.clone(), v))
+                    .collect::<BTreeMap<_, _>>()
+            };
+
+            let both: Vec<_> = left
+                .intersection(&right)
+                .map(|(k, &v, &w)| (k.to_bytes().unwrap(), v, w))
+                .collect();
+            let expected: Vec<_> = lhs
+                .iter()
+                .filter_map(|(k, &v)| rhs.get(k).map(|&w| (k.clone(), v, w)))
+                .collect();
+            assert_eq!(both, expected);
+
+            let difference = only(&lhs, &rhs);
+            assert_eq!(
+                entries(&mut left.difference(&right)),
+                difference.clone().into_iter().collect::<Vec<_>>()
+            );
+
+            let mut symmetric = only(&rhs, &lhs);
+            symmetric.extend(difference.clone());
+            assert_eq!(
+                entries(&mut left.symmetric_difference(&right)),
+                symmetric.into_iter().collect::<Vec<_>>()
+            );
+
+            let mut retained = trie(&lhs);
+            retained.retain_keys_in(&right);
+            let expected: Vec<_> = expected.into_iter().map(|(k, v, _)| (k, v)).collect();
+            assert_eq!(entries(&mut retained.iter()), expected);
+
+            let mut removed = trie(&lhs);
+            removed.remove_keys_in(&right);
+            assert_eq!(
+                entries(&mut removed.iter()),
+                difference.into_iter().collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn set_operations_match_a_model() {
+        check_sets::<ByteKey>();
+        check_sets::<NibbleKey>();
+        check_sets::<BitKey>();
+        check_sets::<RadixKey<5>>();
+    }
+
+    #[test]
+    fn walks_keys_split_at_different_places() {
+        let left: ByteTrie<_> = vec![(&b"abcdef"[..], 0), (b"x", 1)].into_iter().collect();
+        let right: ByteTrie<_> = vec![(&b"abc"[..], 2), (b"abcdeg", 3), (b"abcdef", 4)]
+            .into_iter()
+            .collect();
+
+        let keys = |iter: &mut dyn Iterator<Item = (ByteKey, &usize)>| -> Vec<_> {
+            iter.map(|(k, _)| k.to_bytes().unwrap()).collect()
+        };
+        let both: Vec<_> = left
+            .intersection(&right)
+            .map(|(_, &v, &w)| (v, w))
+            .collect();
+        assert_eq!(both, [(0, 4)]);
+        assert_eq!(keys(&mut left.difference(&right)), [b"x".to_vec()]);
+        assert_eq!(
+            keys(&mut left.symmetric_difference(&right)),
+            [b"abc".to_vec(), b"abcdeg".to_vec(), b"x".to_vec()]
+        );
+    }
+}
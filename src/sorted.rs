@@ -0,0 +1,170 @@
+//! Building tries from keys that are already sorted.
+//!
+//! With every key known up front, each node is created once with its final
+//! compressed key, and its child bucket is sized to the smallest size that
+//! gives every child its own slot.  Tries built this way don't need the empty
+//! keyed nodes that hold collided slots, which inserting one key at a time
+//! creates.
+
+use crate::child::{Child, MAX_CHILD_SIZE};
+use crate::nodes::next_size;
+use crate::{AdaptiveNode, BytesKey};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+
+/// The keys given to `from_sorted_iter` were not sorted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsortedError {
+    /// Position of the first key that was smaller than the key before it
+    pub index: usize,
+}
+
+impl fmt::Display for UnsortedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "key at index {} is smaller than the key before it",
+            self.index
+        )
+    }
+}
+
+impl Error for UnsortedError {}
+
+/// Collect sorted keys, keeping the last value of equal keys like inserting does
+pub(crate) fn collect_sorted<K, V, B, I>(keys: I) -> Result<Vec<(K, Option<V>)>, UnsortedError>
+where
+    K: BytesKey,
+    B: AsRef<[u8]>,
+    I: IntoIterator<Item = (B, V)>,
+{
+    let mut sorted: Vec<(K, Option<V>)> = Vec::new();
+    for (index, (key, value)) in keys.into_iter().enumerate() {
+        let key = K::from_bytes(key.as_ref());
+        let order = sorted.last().map(|(last, _)| last.iter().cmp(key.iter()));
+        match order {
+            Some(Ordering::Equal) => sorted.last_mut().unwrap().1 = Some(value),
+            Some(Ordering::Greater) => return Err(UnsortedError { index }),
+            _ => sorted.push((key, Some(value))),
+        }
+    }
+
+    Ok(sorted)
+}
+
+/// Build a node from strictly sorted keys, skipping the first `depth` key bytes
+pub(crate) fn build<K: BytesKey, V>(
+    keys: &mut [(K, Option<V>)],
+    depth: usize,
+) -> AdaptiveNode<K, V> {
+    // sorted keys share a prefix if and only if the first and last share it
+    let shared = match keys {
+        [] => return AdaptiveNode::default(),
+        [first, .., last] => first.0.common_prefix(&last.0),
+        [only] => only.0.symbol_len(),
+    };
+
+    let fragment = keys[0].0.iter().skip(depth).take(shared - depth);
+    let mut node = AdaptiveNode::new(K::new(fragment.collect()), None);
+    let mut rest = keys;
+    if rest[0].0.symbol_len() == shared {
+        node.value = rest[0].1.take();
+        rest = &mut rest[1..];
+    }
+
+    if rest.is_empty() {
+        return node;
+    }
+
+    let mut children = Vec::new();
+    while !rest.is_empty() {
+        let byte = rest[0].0.symbol(shared);
+        let len = rest
+            .iter()
+            .take_while(|(key, _)| key.symbol(shared) == byte)
+            .count();
+
+        let (group, tail) = rest.split_at_mut(len);
+        children.push((byte, build(group, shared)));
+        rest = tail;
+    }
+
+    let size = bucket_size(children.iter().map(|(byte, _)| *byte));
+    let mut child = Child::new(size);
+    for (byte, node) in children {
+        let slot = child.calculate_slot(byte);
+        child.put(slot, node);
+    }
+
+    node.child = Some(child);
+    node
+}
+
+/// The smallest child size that puts every byte in its own slot
+fn bucket_size(bytes: impl Iterator<Item = u8> + Clone) -> usize {
+    let mut size = 1;
+    while size < MAX_CHILD_SIZE {
+        let mut used = [false; MAX_CHILD_SIZE];
+        let unique = bytes.clone().all(|byte| {
+            let slot = byte as usize % size;
+            !std::mem::replace(&mut used[slot], true)
+        });
+
+        if unique {
+            break;
+        }
+        size = next_size(size);
+    }
+
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::test_util;
+    use rand::prelude::*;
+
+    fn entries(trie: &NibbleTrie<usize>) -> Vec<(Vec<u8>, usize)> {
+        trie.iter()
+            .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+            .collect()
+    }
+
+    #[test]
+    fn matches_inserted_entries() {
+        let mut rng = test_util::rng();
+        let mut random = |count| -> Vec<(Vec<u8>, usize)> {
+            (0..count)
+                .map(|i| ((0..rng.gen_range(0, 6)).map(|_| rng.gen()).collect(), i))
+                .collect()
+        };
+
+        let mut oids = random(2_000);
+        oids.sort();
+
+        let mut inserted: NibbleTrie<_> = oids.iter().cloned().collect();
+        let mut sorted = NibbleTrie::from_sorted_iter(oids).unwrap();
+        assert_eq!(entries(&sorted), entries(&inserted));
+
+        // collided slots show up again once keys are inserted one at a time
+        for (key, value) in random(500) {
+            inserted.insert(&key, value);
+            sorted.insert(&key, value);
+        }
+        assert_eq!(entries(&sorted), entries(&inserted));
+    }
+
+    #[test]
+    fn rejects_unsorted_keys() {
+        let keys = vec![
+            (vec![0x00], 0),
+            (vec![0x00, 0x01], 1),
+            (vec![0x00, 0x01], 2),
+            (vec![0x00, 0x00], 3),
+        ];
+        let err = ByteTrie::from_sorted_iter(keys).unwrap_err();
+        assert_eq!(err.index, 3);
+    }
+}
@@ -0,0 +1,23 @@
+//! Helpers shared by the tests.
+
+use rand::prelude::*;
+
+/// Bytes that share slots in the smaller child buckets, so keys made of them
+/// go through node splits, collisions and empty keyed ancestors
+pub(crate) const COLLIDING: [u8; 6] = [0x00, 0x01, 0x40, 0x80, 0xc0, 0xff];
+
+/// A seeded generator, so failures reproduce
+pub(crate) fn rng() -> StdRng {
+    StdRng::seed_from_u64(0x7472_6965)
+}
+
+/// A key of up to 4 colliding bytes, including the empty key
+pub(crate) fn colliding_key(rng: &mut StdRng) -> Vec<u8> {
+    let len = rng.gen_range(0, 5);
+    (0..len).map(|_| *COLLIDING.choose(rng).unwrap()).collect()
+}
+
+/// `count` colliding keys, which repeat often
+pub(crate) fn colliding_keys(rng: &mut StdRng, count: usize) -> Vec<Vec<u8>> {
+    (0..count).map(|_| colliding_key(rng)).collect()
+}
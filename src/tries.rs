@@ -1,16 +1,22 @@
 //! `u8` based trie implementations.
 
+use crate::cursor::{Cursor, CursorMut};
+use crate::hex::{self, HexError};
+use crate::iter::Iter;
 use crate::keys::{BitKey, ByteKey, NibbleKey};
-pub use crate::BytesTrie;
+use crate::sorted::{self, UnsortedError};
 use crate::{AdaptiveNode, BytesKey};
+pub use crate::{BytesTrie, BytesTrieExt};
+use std::ops::{Bound, RangeBounds};
 
-/// A `u8` based Trie represented with bytes.
+/// A `u8` based Trie over any `BytesKey`, like a
+/// [`RadixKey`](crate::keys::RadixKey) of a custom width.
 #[derive(Debug)]
-pub struct ByteTrie<T> {
-    pub(crate) root: AdaptiveNode<ByteKey, T>,
+pub struct Trie<K: BytesKey, T> {
+    pub(crate) root: AdaptiveNode<K, T>,
 }
 
-impl<T> BytesTrie<T> for ByteTrie<T> {
+impl<K: BytesKey, T> BytesTrie<T> for Trie<K, T> {
     fn new() -> Self {
         Self {
             root: AdaptiveNode::default(),
@@ -18,57 +24,360 @@ impl<T> BytesTrie<T> for ByteTrie<T> {
     }
 
     fn insert(&mut self, key: &[u8], value: T) {
-        let key = ByteKey::from_bytes(&key);
+        let key = K::from_bytes(key);
         self.root.insert(key, Some(value));
     }
 }
 
-impl<T> Default for ByteTrie<T> {
+impl<K: BytesKey, T> Default for Trie<K, T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
+impl<K: BytesKey, T> BytesTrieExt<T> for Trie<K, T> {
+    type Key = K;
+    type Iter<'a>
+        = Iter<'a, K, T>
+    where
+        Self: 'a,
+        T: 'a;
+
+    fn get(&self, key: &[u8]) -> Option<&T> {
+        Trie::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &[u8]) -> Option<&mut T> {
+        Trie::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<T> {
+        Trie::remove(self, key)
+    }
+
+    fn iter(&self) -> Iter<'_, K, T> {
+        Trie::iter(self)
+    }
+
+    fn iter_prefix(&self, prefix: &[u8]) -> Iter<'_, K, T> {
+        Trie::iter_prefix(self, prefix)
+    }
+
+    fn len(&self) -> usize {
+        Trie::len(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        Trie::is_empty(self)
+    }
+}
+
+/// A `u8` based Trie represented with bytes.
+pub type ByteTrie<T> = Trie<ByteKey, T>;
+
 /// A `u8` based Trie represented with nibbles.
-#[derive(Debug)]
-pub struct NibbleTrie<T> {
-    pub(crate) root: AdaptiveNode<NibbleKey, T>,
+pub type NibbleTrie<T> = Trie<NibbleKey, T>;
+
+/// A `u8` based Trie represented with bits.
+pub type BitTrie<T> = Trie<BitKey, T>;
+
+/// Convert a byte range bound into a bound over `K`'s representation
+fn key_bound<K: BytesKey>(bound: Bound<&&[u8]>) -> Bound<Vec<u8>> {
+    match bound {
+        Bound::Included(bytes) => Bound::Included(K::symbols(bytes).collect()),
+        Bound::Excluded(bytes) => Bound::Excluded(K::symbols(bytes).collect()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
 }
 
-impl<T> BytesTrie<T> for NibbleTrie<T> {
-    fn new() -> Self {
-        Self {
-            root: AdaptiveNode::default(),
+impl<K: BytesKey, T> Trie<K, T> {
+    /// Build a trie from keys sorted by their bytes, in linear time.
+    ///
+    /// Every node is created once with its final key and a child
+    /// bucket just large enough for its children.  Equal keys keep the
+    /// last value, like inserting them would.
+    pub fn from_sorted_iter<B, I>(keys: I) -> Result<Self, UnsortedError>
+    where
+        B: AsRef<[u8]>,
+        I: IntoIterator<Item = (B, T)>,
+    {
+        let mut keys = sorted::collect_sorted(keys)?;
+        Ok(Self {
+            root: sorted::build(&mut keys, 0),
+        })
+    }
+
+    /// Get a reference to the value of a key
+    pub fn get(&self, key: &[u8]) -> Option<&T> {
+        self.root.get_symbols(K::symbols(key))
+    }
+
+    /// Get a mutable reference to the value of a key
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut T> {
+        self.root.get_symbols_mut(K::symbols(key))
+    }
+
+    /// If the trie contains a value for the key
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Remove a key from the trie, returning its value if it existed
+    pub fn remove(&mut self, key: &[u8]) -> Option<T> {
+        self.root.remove_symbols(K::symbols(key))
+    }
+
+    /// Insert a key given in key representation, like symbols of a
+    /// `RadixKey` that don't fill whole bytes
+    pub fn insert_key(&mut self, key: K, value: T) {
+        self.root.insert(key, Some(value));
+    }
+
+    /// Get a reference to the value of a key given in key representation
+    pub fn get_key(&self, key: &K) -> Option<&T> {
+        self.root.get_symbols(key.iter())
+    }
+
+    /// Remove a key given in key representation, returning its value if
+    /// it existed
+    pub fn remove_key(&mut self, key: &K) -> Option<T> {
+        self.root.remove_symbols(key.iter())
+    }
+
+    /// The entry with the smallest key
+    pub fn first_key_value(&self) -> Option<(K, &T)> {
+        self.iter().next()
+    }
+
+    /// The entry with the largest key
+    pub fn last_key_value(&self) -> Option<(K, &T)> {
+        self.iter().next_back()
+    }
+
+    /// Remove and return the entry with the smallest key
+    pub fn pop_first(&mut self) -> Option<(K, T)> {
+        let (key, _) = self.first_key_value()?;
+        let value = self.root.remove_symbols(key.iter())?;
+        Some((key, value))
+    }
+
+    /// Remove and return the entry with the largest key
+    pub fn pop_last(&mut self) -> Option<(K, T)> {
+        let (key, _) = self.last_key_value()?;
+        let value = self.root.remove_symbols(key.iter())?;
+        Some((key, value))
+    }
+
+    /// A cursor positioned before the first entry
+    pub fn cursor(&self) -> Cursor<'_, K, T> {
+        self.root.cursor()
+    }
+
+    /// A mutable cursor positioned before the first entry
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, K, T> {
+        self.root.cursor_mut()
+    }
+
+    /// The number of keys in the trie.
+    ///
+    /// Walks every node, the trie doesn't keep a count.
+    pub fn len(&self) -> usize {
+        self.root.len()
+    }
+
+    /// If the trie has no keys
+    pub fn is_empty(&self) -> bool {
+        self.root.is_empty()
+    }
+
+    /// Iterate over all entries in key order
+    pub fn iter(&self) -> Iter<'_, K, T> {
+        self.root.iter()
+    }
+
+    /// Iterate over the entries with keys inside `range`, in key order.
+    ///
+    /// Subtrees that fall outside of the range are skipped entirely.
+    pub fn range<'r, R>(&self, range: R) -> Iter<'_, K, T>
+    where
+        R: RangeBounds<&'r [u8]>,
+    {
+        let start = key_bound::<K>(range.start_bound());
+        let end = key_bound::<K>(range.end_bound());
+        Iter::new(&self.root, start, end)
+    }
+
+    /// Merge two tries, combining the values of keys in both with `resolve`.
+    ///
+    /// `resolve` gets the key, the value of this trie and the value of
+    /// `other`.  Subtrees of `other` that don't overlap with this trie are
+    /// moved over whole instead of inserting their keys one by one.
+    pub fn merge<F>(mut self, other: Self, mut resolve: F) -> Self
+    where
+        F: FnMut(&K, T, T) -> T,
+    {
+        self.root.merge(other.root, &mut Vec::new(), &mut resolve);
+        self
+    }
+
+    /// Move every entry of `other` into the trie, leaving `other` empty.
+    ///
+    /// Keys in both tries get the value of `other`, like `BTreeMap::append`.
+    pub fn append(&mut self, other: &mut Self) {
+        let other = std::mem::take(&mut other.root);
+        self.root
+            .merge(other, &mut Vec::new(), &mut |_, _, value| value);
+    }
+
+    /// Iterate over the entries with keys starting with `prefix`, in key order
+    pub fn iter_prefix(&self, prefix: &[u8]) -> Iter<'_, K, T> {
+        // every key starting with `prefix` sorts before the prefix with its
+        // last byte below 0xff incremented
+        let mut end = prefix.to_vec();
+        while end.last() == Some(&0xff) {
+            end.pop();
         }
+        if let Some(last) = end.last_mut() {
+            *last += 1;
+        }
+
+        let end = if end.is_empty() {
+            Bound::Unbounded
+        } else {
+            Bound::Excluded(&end[..])
+        };
+        self.range((Bound::Included(prefix), end))
     }
+}
 
-    fn insert(&mut self, key: &[u8], value: T) {
-        let key = NibbleKey::from_bytes(&key);
-        self.root.insert(key, Some(value))
+impl<B: AsRef<[u8]>, K: BytesKey, T> Extend<(B, T)> for Trie<K, T> {
+    fn extend<I: IntoIterator<Item = (B, T)>>(&mut self, iter: I) {
+        iter.into_iter()
+            .for_each(|(key, value)| self.insert(key.as_ref(), value));
     }
 }
 
-/// A `u8` based Trie represented with bits.
-#[derive(Debug)]
-pub struct BitTrie<T> {
-    pub(crate) root: AdaptiveNode<BitKey, T>,
+impl<B: AsRef<[u8]>, K: BytesKey, T> std::iter::FromIterator<(B, T)> for Trie<K, T> {
+    fn from_iter<I: IntoIterator<Item = (B, T)>>(iter: I) -> Self {
+        let mut trie = Self::new();
+        trie.extend(iter);
+        trie
+    }
 }
 
-impl<T> BytesTrie<T> for BitTrie<T> {
-    fn new() -> Self {
-        Self {
-            root: AdaptiveNode::default(),
-        }
+impl<'a, K: BytesKey, T> IntoIterator for &'a Trie<K, T> {
+    type Item = (K, &'a T);
+    type IntoIter = Iter<'a, K, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
+}
 
-    fn insert(&mut self, key: &[u8], value: T) {
-        let key = BitKey::from_bytes(&key);
-        self.root.insert(key, Some(value));
+impl<K: BytesKey, T> Trie<K, T> {
+    /// Insert a key given as a hex string
+    pub fn insert_hex(&mut self, key: &str, value: T) -> Result<(), HexError> {
+        self.root.insert(hex::decode_key(key)?, Some(value));
+        Ok(())
+    }
+
+    /// Get a reference to the value of a key given as a hex string
+    pub fn get_hex(&self, key: &str) -> Result<Option<&T>, HexError> {
+        let key: K = hex::decode_key(key)?;
+        Ok(self.root.get_symbols(key.iter()))
+    }
+
+    /// Remove a key given as a hex string, returning its value if it existed
+    pub fn remove_hex(&mut self, key: &str) -> Result<Option<T>, HexError> {
+        let key: K = hex::decode_key(key)?;
+        Ok(self.root.remove_symbols(key.iter()))
     }
 }
 
-impl<T> Default for BitTrie<T> {
-    fn default() -> Self {
-        Self::new()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::RadixKey;
+
+    #[test]
+    fn pops_from_both_ends() {
+        let mut trie = NibbleTrie::new();
+        for (i, key) in [[0x80, 0x00], [0x00, 0x01], [0x80, 0x10], [0x00, 0x00]]
+            .iter()
+            .enumerate()
+        {
+            trie.insert(key, i);
+        }
+
+        assert_eq!(trie.first_key_value().map(|(_, &v)| v), Some(3));
+        assert_eq!(trie.last_key_value().map(|(_, &v)| v), Some(2));
+
+        let (key, value) = trie.pop_first().unwrap();
+        assert_eq!((key.to_bytes(), value), (Some(vec![0, 0]), 3));
+        assert_eq!(trie.pop_last().map(|(_, v)| v), Some(2));
+        assert_eq!(trie.pop_last().map(|(_, v)| v), Some(0));
+        assert_eq!(trie.pop_first().map(|(_, v)| v), Some(1));
+        assert!(trie.pop_first().is_none());
+        assert!(trie.last_key_value().is_none());
+    }
+
+    #[test]
+    fn named_tries_share_the_generic_api() {
+        fn values<K: BytesKey>(trie: &Trie<K, usize>) -> Vec<usize> {
+            trie.iter().map(|(_, &v)| v).collect()
+        }
+
+        let mut nibbles = NibbleTrie::default();
+        let mut bits: BitTrie<_> = Default::default();
+        for (i, key) in [&[0x10][..], &[0x01], &[0x10, 0x00]].iter().enumerate() {
+            nibbles.insert(key, i);
+            bits.insert(key, i);
+        }
+
+        assert_eq!(values(&nibbles), [1, 0, 2]);
+        assert_eq!(values(&bits), values(&nibbles));
+        assert_eq!(nibbles.remove(&[0x10]), bits.remove(&[0x10]));
+    }
+
+    #[test]
+    fn radix_keys_of_any_width() {
+        // dna bases as 2 bit symbols, in the order A, C, G, T
+        let base = |sequence: &str| -> RadixKey<2> {
+            RadixKey::new(
+                sequence
+                    .bytes()
+                    .map(|b| b"ACGT".iter().position(|&c| c == b).unwrap() as u8)
+                    .collect(),
+            )
+        };
+
+        let mut trie = Trie::new();
+        for (i, sequence) in ["GATTACA", "GAT", "CAT", "GATT", "TAG"].iter().enumerate() {
+            trie.insert_key(base(sequence), i);
+        }
+        assert_eq!(trie.get_key(&base("GATT")), Some(&3));
+        assert_eq!(trie.get_key(&base("GA")), None);
+        assert_eq!(trie.remove_key(&base("CAT")), Some(2));
+
+        let values: Vec<_> = trie.iter().map(|(_, &v)| v).collect();
+        assert_eq!(values, [1, 3, 0, 4]);
+        assert_eq!(
+            trie.first_key_value().map(|(k, _)| k.to_string()),
+            Some("203".to_string())
+        );
+
+        // whole bytes read as 6 bit digits still behave like byte keys
+        let trie: Trie<RadixKey<6>, _> = vec![(&[0xff, 0x00][..], 0), (&[0xff], 1), (&[0x00], 2)]
+            .into_iter()
+            .collect();
+        let keys: Vec<_> = trie
+            .iter()
+            .map(|(k, &v)| (k.to_bytes().unwrap(), v))
+            .collect();
+        assert_eq!(
+            keys,
+            [(vec![0x00], 2), (vec![0xff], 1), (vec![0xff, 0x00], 0)]
+        );
+        assert_eq!(trie.get(&[0xff]), Some(&1));
     }
 }
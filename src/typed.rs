@@ -0,0 +1,386 @@
+//! Tries keyed by typed values instead of raw bytes.
+//!
+//! A [`TrieKey`] encodes a value into bytes that sort the same way as the
+//! values do, so a [`TypedTrie`] iterates in the key type's order.  Unsigned
+//! integers are big-endian, signed integers are big-endian with the sign bit
+//! flipped.  Strings and byte vectors escape `0x00` as `0x00 0xff` and end in
+//! `0x00 0x00`, which keeps a string sorting before any longer string it is a
+//! prefix of and lets tuples simply concatenate their fields.
+
+use crate::iter::Iter;
+use crate::keys::ByteKey;
+use crate::tries::ByteTrie;
+use crate::{BytesKey, BytesTrie};
+use std::convert::TryInto;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+
+/// Values with an order-preserving byte encoding.
+///
+/// `decode` has to accept everything `encode` writes, a [`TypedTrie`] relies
+/// on it to hand out its keys again.
+pub trait TrieKey: Sized {
+    /// Append the encoding of the value to `bytes`
+    fn encode(&self, bytes: &mut Vec<u8>);
+
+    /// Decode a value from the front of `bytes`, advancing past it
+    fn decode(bytes: &mut &[u8]) -> Option<Self>;
+
+    /// The encoding of the value
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.encode(&mut bytes);
+        bytes
+    }
+
+    /// Decode a value that takes up all of `bytes`
+    fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let value = Self::decode(&mut bytes)?;
+        if bytes.is_empty() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
+/// Split `N` bytes off the front of `bytes`
+fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    if bytes.len() < N {
+        return None;
+    }
+
+    let (front, rest) = bytes.split_at(N);
+    *bytes = rest;
+    front.try_into().ok()
+}
+
+macro_rules! impl_unsigned {
+    ($($int:ty),*) => {$(
+        impl TrieKey for $int {
+            fn encode(&self, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(&self.to_be_bytes());
+            }
+
+            fn decode(bytes: &mut &[u8]) -> Option<Self> {
+                take(bytes).map(<$int>::from_be_bytes)
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_signed {
+    ($($int:ty => $unsigned:ty),*) => {$(
+        impl TrieKey for $int {
+            fn encode(&self, bytes: &mut Vec<u8>) {
+                // flipping the sign bit puts negative values first
+                let flipped = (*self as $unsigned) ^ (1 << (<$unsigned>::BITS - 1));
+                flipped.encode(bytes);
+            }
+
+            fn decode(bytes: &mut &[u8]) -> Option<Self> {
+                let flipped = <$unsigned>::decode(bytes)?;
+                Some((flipped ^ (1 << (<$unsigned>::BITS - 1))) as $int)
+            }
+        }
+    )*};
+}
+
+impl_unsigned!(u8, u16, u32, u64, u128);
+impl_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+impl TrieKey for Vec<u8> {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        for &byte in self {
+            bytes.push(byte);
+            if byte == 0x00 {
+                bytes.push(0xff);
+            }
+        }
+        bytes.extend_from_slice(&[0x00, 0x00]);
+    }
+
+    fn decode(bytes: &mut &[u8]) -> Option<Self> {
+        let mut decoded = Vec::new();
+        loop {
+            match take(bytes)? {
+                [0x00] => match take(bytes)? {
+                    [0x00] => return Some(decoded),
+                    [0xff] => decoded.push(0x00),
+                    _ => return None,
+                },
+                [byte] => decoded.push(byte),
+            }
+        }
+    }
+}
+
+impl TrieKey for String {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        // utf-8 byte order is code point order
+        self.as_bytes().to_vec().encode(bytes);
+    }
+
+    fn decode(bytes: &mut &[u8]) -> Option<Self> {
+        String::from_utf8(Vec::decode(bytes)?).ok()
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($field:ident),*) => {
+        impl<$($field: TrieKey),*> TrieKey for ($($field,)*) {
+            #[allow(non_snake_case)]
+            fn encode(&self, bytes: &mut Vec<u8>) {
+                let ($($field,)*) = self;
+                $($field.encode(bytes);)*
+            }
+
+            fn decode(bytes: &mut &[u8]) -> Option<Self> {
+                Some(($($field::decode(bytes)?,)*))
+            }
+        }
+    };
+}
+
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+
+/// A trie of typed keys, stored in a [`ByteTrie`] by their [`TrieKey`] encoding
+#[derive(Debug)]
+pub struct TypedTrie<K: TrieKey, V> {
+    trie: ByteTrie<V>,
+    keys: PhantomData<K>,
+}
+
+impl<K: TrieKey, V> TypedTrie<K, V> {
+    /// Create an empty trie
+    pub fn new() -> Self {
+        Self {
+            trie: ByteTrie::new(),
+            keys: PhantomData,
+        }
+    }
+
+    /// Insert a key, replacing the value of an existing one
+    pub fn insert(&mut self, key: &K, value: V) {
+        self.trie.insert(&key.to_bytes(), value);
+    }
+
+    /// Get a reference to the value of a key
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.trie.get(&key.to_bytes())
+    }
+
+    /// Get a mutable reference to the value of a key
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.trie.get_mut(&key.to_bytes())
+    }
+
+    /// If the trie contains a value for the key
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Remove a key from the trie, returning its value if it existed
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.trie.remove(&key.to_bytes())
+    }
+
+    /// The entry with the smallest key
+    pub fn first_key_value(&self) -> Option<(K, &V)> {
+        self.iter().next()
+    }
+
+    /// The entry with the largest key
+    pub fn last_key_value(&self) -> Option<(K, &V)> {
+        self.iter().next_back()
+    }
+
+    /// Iterate over all entries in key order
+    pub fn iter(&self) -> TypedIter<'_, K, V> {
+        TypedIter::new(self.trie.iter())
+    }
+
+    /// Iterate over the entries with keys inside `range`, in key order
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> TypedIter<'_, K, V> {
+        let start = range.start_bound().map(K::to_bytes);
+        let end = range.end_bound().map(K::to_bytes);
+        TypedIter::new(self.trie.range((
+            start.as_ref().map(Vec::as_slice),
+            end.as_ref().map(Vec::as_slice),
+        )))
+    }
+}
+
+impl<K: TrieKey, V> Default for TypedTrie<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: TrieKey, V> Extend<(K, V)> for TypedTrie<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        iter.into_iter()
+            .for_each(|(key, value)| self.insert(&key, value));
+    }
+}
+
+impl<K: TrieKey, V> std::iter::FromIterator<(K, V)> for TypedTrie<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut trie = Self::new();
+        trie.extend(iter);
+        trie
+    }
+}
+
+impl<'a, K: TrieKey, V> IntoIterator for &'a TypedTrie<K, V> {
+    type Item = (K, &'a V);
+    type IntoIter = TypedIter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An ordered, double-ended iterator over the decoded entries of a [`TypedTrie`].
+///
+/// Panics if a key doesn't decode, which only a [`TrieKey`] that can't decode
+/// its own encoding leads to.
+pub struct TypedIter<'a, K: TrieKey, V> {
+    iter: Iter<'a, ByteKey, V>,
+    keys: PhantomData<K>,
+}
+
+impl<'a, K: TrieKey, V> TypedIter<'a, K, V> {
+    /// Decode the keys of `iter`, which must all be encodings of `K`.
+    ///
+    /// Only a `TypedTrie` creates these, and it only inserts keys it encoded
+    /// itself, so the keys decode as long as `K` keeps the `TrieKey` contract.
+    fn new(iter: Iter<'a, ByteKey, V>) -> Self {
+        Self {
+            iter,
+            keys: PhantomData,
+        }
+    }
+}
+
+/// Decode a key the trie encoded itself
+fn decode<K: TrieKey>(key: ByteKey) -> K {
+    key.to_bytes()
+        .as_deref()
+        .and_then(K::from_bytes)
+        .expect("a TrieKey didn't decode its own encoding")
+}
+
+impl<'a, K: TrieKey, V> Iterator for TypedIter<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, value)| (decode(key), value))
+    }
+}
+
+impl<'a, K: TrieKey, V> DoubleEndedIterator for TypedIter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next_back()
+            .map(|(key, value)| (decode(key), value))
+    }
+}
+
+impl<'a, K: TrieKey, V> std::iter::FusedIterator for TypedIter<'a, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use rand::prelude::*;
+    use std::collections::BTreeMap;
+    use std::fmt::Debug;
+
+    fn check_order<K: TrieKey + Ord + Clone + Debug>(keys: Vec<K>) {
+        let model: BTreeMap<_, _> = keys.iter().cloned().zip(0..).collect();
+        let trie: TypedTrie<_, _> = keys.into_iter().zip(0..).collect();
+
+        let entries: Vec<_> = trie.iter().map(|(k, &v)| (k, v)).collect();
+        let expected: Vec<_> = model.into_iter().collect();
+        assert_eq!(entries, expected);
+
+        for (key, value) in &expected {
+            assert_eq!(K::from_bytes(&key.to_bytes()).as_ref(), Some(key));
+            assert_eq!(trie.get(key), Some(value));
+        }
+    }
+
+    #[test]
+    fn iterates_in_key_order() {
+        let mut rng = test_util::rng();
+        check_order((0..500).map(|_| rng.gen::<u32>()).collect());
+        check_order(
+            (0..500)
+                .map(|_| rng.gen::<i64>() >> rng.gen_range(0, 64))
+                .chain(vec![i64::MIN, -1, 0, 1, i64::MAX])
+                .collect(),
+        );
+
+        let strings = ["", "a", "a\0", "a\0b", "a\u{1}", "ab", "b", "é", "\0"];
+        check_order(strings.iter().map(|s| s.to_string()).collect());
+
+        check_order(
+            (0..500)
+                .map(|_| {
+                    let name = strings.choose(&mut rng).unwrap().to_string();
+                    (name, rng.gen_range(-3i8, 3), rng.gen::<u8>() % 4)
+                })
+                .collect(),
+        );
+    }
+
+    #[test]
+    fn ranges_over_typed_keys() {
+        let trie: TypedTrie<(u32, String), ()> = (0..10)
+            .flat_map(|n| vec![(n, String::new()), (n, "x".to_string())])
+            .map(|key| (key, ()))
+            .collect();
+
+        let keys: Vec<_> = trie
+            .range((3, "x".to_string())..(5, "x".to_string()))
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (3, "x".to_string()),
+                (4, String::new()),
+                (4, "x".to_string()),
+                (5, String::new())
+            ]
+        );
+        assert_eq!(trie.last_key_value().map(|(k, _)| k.0), Some(9));
+        assert_eq!(<(u32, String)>::from_bytes(&[0, 0, 0]), None);
+    }
+
+    /// Encodes two bytes but only decodes one
+    #[derive(Debug)]
+    struct Lossy;
+
+    impl TrieKey for Lossy {
+        fn encode(&self, bytes: &mut Vec<u8>) {
+            bytes.extend_from_slice(&[1, 2]);
+        }
+
+        fn decode(bytes: &mut &[u8]) -> Option<Self> {
+            take::<1>(bytes).map(|_| Lossy)
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "a TrieKey didn't decode its own encoding")]
+    fn iterating_needs_keys_to_decode() {
+        let mut trie = TypedTrie::new();
+        trie.insert(&Lossy, ());
+        assert!(trie.contains_key(&Lossy));
+        trie.iter().for_each(drop);
+    }
+}